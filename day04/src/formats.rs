@@ -0,0 +1,376 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use records::records;
+use serde_json::{Map, Value};
+
+use crate::passport::{Location, Passport};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Batch,
+    Jsonl,
+    Csv,
+}
+
+impl Format {
+    pub fn from_path(path: &str) -> Format {
+        if path.ends_with(".jsonl") || path.ends_with(".json") {
+            Format::Jsonl
+        } else if path.ends_with(".csv") {
+            Format::Csv
+        } else {
+            Format::Batch
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FormatError {
+    Io(io::Error),
+    Json {
+        line: usize,
+        error: serde_json::Error,
+    },
+    Csv(csv::Error),
+    UnsupportedValue {
+        line: usize,
+        field: String,
+    },
+    /// `what` describes the part of a passport the format has no way to
+    /// write.
+    Unrepresentable {
+        format: Format,
+        what: String,
+    },
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::Io(e) => write!(f, "{}", e),
+            FormatError::Json { line, error } => write!(f, "line {}: {}", line, error),
+            FormatError::Csv(e) => write!(f, "{}", e),
+            FormatError::UnsupportedValue { line, field } => {
+                write!(f, "line {}: field {} must be a string", line, field)
+            }
+            FormatError::Unrepresentable { format, what } => write!(
+                f,
+                "{} can't be written in {} format",
+                what,
+                match format {
+                    Format::Batch => "batch",
+                    Format::Jsonl => "JSONL",
+                    Format::Csv => "CSV",
+                }
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for FormatError {
+    fn from(e: io::Error) -> Self {
+        FormatError::Io(e)
+    }
+}
+
+impl From<csv::Error> for FormatError {
+    fn from(e: csv::Error) -> Self {
+        FormatError::Csv(e)
+    }
+}
+
+pub fn read<R: BufRead>(format: Format, reader: R) -> Result<Vec<Passport>, FormatError> {
+    match format {
+        Format::Batch => read_batch(reader),
+        Format::Jsonl => read_jsonl(reader),
+        Format::Csv => read_csv(reader),
+    }
+}
+
+pub fn write<W: Write>(
+    format: Format,
+    writer: W,
+    passports: &[Passport],
+) -> Result<(), FormatError> {
+    match format {
+        Format::Batch => write_batch(writer, passports),
+        Format::Jsonl => write_jsonl(writer, passports),
+        Format::Csv => write_csv(writer, passports),
+    }
+}
+
+fn read_batch<R: BufRead>(reader: R) -> Result<Vec<Passport>, FormatError> {
+    let mut passports = Vec::new();
+    for (idx, r) in records(reader).enumerate() {
+        let record = r?;
+        let mut passport = Passport::new();
+        passport.location = Some(Location {
+            record: idx + 1,
+            first_line: record.first_line,
+            last_line: record.last_line(),
+        });
+        for (line_number, kv) in record.key_values() {
+            match kv {
+                Ok((name, value)) => passport.add_field(name, value.to_string()),
+                Err(token) => {
+                    eprintln!("Ignoring malformed field {} on line {}", token, line_number)
+                }
+            }
+        }
+        passports.push(passport);
+    }
+    Ok(passports)
+}
+
+fn write_batch<W: Write>(mut writer: W, passports: &[Passport]) -> Result<(), FormatError> {
+    for (idx, passport) in passports.iter().enumerate() {
+        if idx > 0 {
+            writeln!(writer)?;
+        }
+        // a blank line would end the previous record instead
+        if passport.fields.is_empty() {
+            return Err(FormatError::Unrepresentable {
+                format: Format::Batch,
+                what: "a passport with no fields".to_string(),
+            });
+        }
+        let mut kvs: Vec<String> = Vec::new();
+        for (name, value) in passport.fields.iter() {
+            if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == ':') {
+                return Err(FormatError::Unrepresentable {
+                    format: Format::Batch,
+                    what: format!("field name '{}'", name),
+                });
+            }
+            if value.is_empty() || value.contains(char::is_whitespace) {
+                return Err(FormatError::Unrepresentable {
+                    format: Format::Batch,
+                    what: format!("field {} value '{}'", name, value),
+                });
+            }
+            kvs.push(format!("{}:{}", name, value));
+        }
+        writeln!(writer, "{}", kvs.join(" "))?;
+    }
+    Ok(())
+}
+
+fn read_jsonl<R: BufRead>(reader: R) -> Result<Vec<Passport>, FormatError> {
+    let mut passports = Vec::new();
+    for (idx, r) in reader.lines().enumerate() {
+        let line = r?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_number = idx + 1;
+        let object: Map<String, Value> =
+            serde_json::from_str(&line).map_err(|error| FormatError::Json {
+                line: line_number,
+                error,
+            })?;
+
+        let mut passport = Passport::new();
+        passport.location = Some(Location {
+            record: passports.len() + 1,
+            first_line: line_number,
+            last_line: line_number,
+        });
+        for (name, value) in object {
+            // numbers would come back as strings, so they aren't accepted
+            let value = match value {
+                Value::String(s) => s,
+                _ => {
+                    return Err(FormatError::UnsupportedValue {
+                        line: line_number,
+                        field: name,
+                    })
+                }
+            };
+            passport.add_field(&name, value);
+        }
+        passports.push(passport);
+    }
+    Ok(passports)
+}
+
+fn write_jsonl<W: Write>(mut writer: W, passports: &[Passport]) -> Result<(), FormatError> {
+    for passport in passports {
+        let object: Map<String, Value> = passport
+            .fields
+            .iter()
+            .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+            .collect();
+        writeln!(writer, "{}", Value::Object(object))?;
+    }
+    Ok(())
+}
+
+// An empty cell means the passport doesn't have that field.
+fn read_csv<R: BufRead>(reader: R) -> Result<Vec<Passport>, FormatError> {
+    let mut csv_reader = csv::Reader::from_reader(reader);
+    let headers = csv_reader.headers()?.clone();
+
+    let mut passports = Vec::new();
+    for r in csv_reader.records() {
+        let row = r?;
+        let line = row.position().map_or(0, |p| p.line() as usize);
+        let mut passport = Passport::new();
+        passport.location = Some(Location {
+            record: passports.len() + 1,
+            first_line: line,
+            last_line: line,
+        });
+        for (name, value) in headers.iter().zip(row.iter()) {
+            if !value.is_empty() {
+                passport.add_field(name, value.to_string());
+            }
+        }
+        passports.push(passport);
+    }
+    Ok(passports)
+}
+
+// An empty value would read back as a missing field, so it's refused.
+fn write_csv<W: Write>(writer: W, passports: &[Passport]) -> Result<(), FormatError> {
+    for passport in passports {
+        if let Some((name, _)) = passport.fields.iter().find(|(_, v)| v.is_empty()) {
+            return Err(FormatError::Unrepresentable {
+                format: Format::Csv,
+                what: format!("field {} with an empty value", name),
+            });
+        }
+    }
+    let columns: BTreeSet<&String> = passports.iter().flat_map(|p| p.fields.keys()).collect();
+
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    csv_writer.write_record(columns.iter())?;
+    for passport in passports {
+        csv_writer.write_record(
+            columns
+                .iter()
+                .map(|c| passport.fields.get(*c).map_or("", String::as_str)),
+        )?;
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formats::{read, write, Format, FormatError};
+    use crate::passport::Passport;
+
+    const BATCH: &str = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd\n\
+                         byr:1937 iyr:2017 cid:147 hgt:183cm\n\
+                         \n\
+                         iyr:2013 ecl:amb nat:\"uk,fr\" pid:028048884\n\
+                         hcl:#cfa07d byr:1929\n";
+
+    fn convert(passports: &[Passport], format: Format) -> Vec<Passport> {
+        let mut out: Vec<u8> = Vec::new();
+        write(format, &mut out, passports).unwrap();
+        read(format, out.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let passports = read(Format::Batch, BATCH.as_bytes()).unwrap();
+        assert_eq!(2, passports.len());
+        assert_eq!(
+            Some("\"uk,fr\""),
+            passports[1].fields.get("nat").map(String::as_str)
+        );
+
+        for format in [Format::Batch, Format::Jsonl, Format::Csv].iter() {
+            let converted = convert(&passports, *format);
+            assert_eq!(passports.len(), converted.len());
+            for (a, b) in passports.iter().zip(converted.iter()) {
+                assert_eq!(a.fields, b.fields, "{:?}", format);
+            }
+        }
+
+        let via_all = convert(
+            &convert(&convert(&passports, Format::Jsonl), Format::Csv),
+            Format::Batch,
+        );
+        let mut out: Vec<u8> = Vec::new();
+        write(Format::Batch, &mut out, &via_all).unwrap();
+        assert_eq!(
+            "byr:1937 cid:147 ecl:gry eyr:2020 hcl:#fffffd hgt:183cm iyr:2017 pid:860033327\n\
+             \n\
+             byr:1929 ecl:amb hcl:#cfa07d iyr:2013 nat:\"uk,fr\" pid:028048884\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        // an empty value survives JSONL, but CSV can't tell it from a
+        // missing field
+        let empty = read(
+            Format::Jsonl,
+            "{\"pid\":\"000000001\",\"cid\":\"\"}\n".as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(empty[0].fields, convert(&empty, Format::Jsonl)[0].fields);
+        let mut out: Vec<u8> = Vec::new();
+        assert!(matches!(
+            write(Format::Csv, &mut out, &empty),
+            Err(FormatError::Unrepresentable {
+                format: Format::Csv,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_read_jsonl() {
+        let input = "{\"byr\": \"1937\", \"hgt\": \"183cm\"}\n\n{\"pid\": \"000000001\"}\n";
+        let passports = read(Format::Jsonl, input.as_bytes()).unwrap();
+        assert_eq!(2, passports.len());
+        assert_eq!("1937", passports[0].fields["byr"]);
+        assert_eq!(3, passports[1].location.unwrap().first_line);
+
+        let nested = read(Format::Jsonl, "{\"byr\": [1937]}\n".as_bytes());
+        assert!(matches!(
+            nested,
+            Err(FormatError::UnsupportedValue { line: 1, .. })
+        ));
+        // a number would be written back out as a string
+        match read(
+            Format::Jsonl,
+            "{\"pid\": \"1\"}\n{\"byr\": 1980}\n".as_bytes(),
+        ) {
+            Err(e) => assert_eq!("line 2: field byr must be a string", e.to_string()),
+            Ok(_) => panic!("numbers should be rejected"),
+        }
+
+        let broken = read(Format::Jsonl, "{}\n{\"byr\"\n".as_bytes());
+        assert!(matches!(broken, Err(FormatError::Json { line: 2, .. })));
+    }
+
+    #[test]
+    fn test_unrepresentable_batch_value() {
+        let mut passport = Passport::new();
+        passport.add_field("nat", "United Kingdom".to_string());
+        let mut out: Vec<u8> = Vec::new();
+        let result = write(Format::Batch, &mut out, &[passport]);
+        assert!(matches!(result, Err(FormatError::Unrepresentable { .. })));
+
+        // names that would split into other fields, and passports that
+        // would vanish
+        for name in ["birth year", "byr:x", ""].iter() {
+            let mut passport = Passport::new();
+            passport.add_field(name, "1980".to_string());
+            let result = write(Format::Batch, &mut out, &[passport]);
+            assert_eq!(
+                format!("field name '{}' can't be written in batch format", name),
+                result.unwrap_err().to_string()
+            );
+        }
+        let result = write(Format::Batch, &mut out, &[Passport::new()]);
+        assert_eq!(
+            "a passport with no fields can't be written in batch format",
+            result.unwrap_err().to_string()
+        );
+    }
+}
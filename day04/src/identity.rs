@@ -0,0 +1,183 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+use crate::passport::{Location, Passport};
+
+#[derive(Debug, PartialEq)]
+pub struct Conflict {
+    pub field: String,
+    pub values: Vec<(Option<Location>, String)>,
+}
+
+/// Records that share an identity. Groups without conflicts are merged into
+/// a single passport; groups with conflicts are dropped.
+#[derive(Debug)]
+pub struct DuplicateGroup {
+    pub identity: String,
+    pub key: String,
+    pub locations: Vec<Option<Location>>,
+    pub conflicts: Vec<Conflict>,
+}
+
+pub struct Resolution {
+    pub passports: Vec<Passport>,
+    pub duplicates: Vec<DuplicateGroup>,
+}
+
+pub fn resolve(passports: Vec<Passport>, identity: &str) -> Resolution {
+    let mut groups: Vec<Vec<Passport>> = Vec::new();
+    let mut group_for_key: HashMap<String, usize> = HashMap::new();
+    for passport in passports {
+        match passport.fields.get(identity) {
+            Some(key) => match group_for_key.get(key) {
+                Some(&idx) => groups[idx].push(passport),
+                None => {
+                    group_for_key.insert(key.clone(), groups.len());
+                    groups.push(vec![passport]);
+                }
+            },
+            None => groups.push(vec![passport]),
+        }
+    }
+
+    let mut resolution = Resolution {
+        passports: Vec::new(),
+        duplicates: Vec::new(),
+    };
+    for group in groups {
+        if group.len() == 1 {
+            resolution.passports.extend(group);
+            continue;
+        }
+
+        let conflicts = find_conflicts(&group);
+        let consistent = conflicts.is_empty();
+        resolution.duplicates.push(DuplicateGroup {
+            identity: identity.to_string(),
+            key: group[0].fields[identity].clone(),
+            locations: group.iter().map(|p| p.location).collect(),
+            conflicts,
+        });
+        if consistent {
+            resolution.passports.push(merge(group));
+        }
+    }
+    resolution
+}
+
+// A field that's missing from some records isn't a conflict, only a field
+// with more than one distinct value.
+fn find_conflicts(group: &[Passport]) -> Vec<Conflict> {
+    let names: BTreeSet<&String> = group.iter().flat_map(|p| p.fields.keys()).collect();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let values: Vec<(Option<Location>, String)> = group
+                .iter()
+                .filter_map(|p| p.fields.get(name).map(|v| (p.location, v.clone())))
+                .collect();
+            let distinct: BTreeSet<&String> = values.iter().map(|(_, v)| v).collect();
+            if distinct.len() > 1 {
+                Some(Conflict {
+                    field: name.clone(),
+                    values,
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn merge(group: Vec<Passport>) -> Passport {
+    let mut merged = Passport::new();
+    merged.location = group[0].location;
+    let fields: BTreeMap<String, String> = group.into_iter().flat_map(|p| p.fields).collect();
+    merged.fields = fields;
+    merged
+}
+
+fn describe(location: &Option<Location>) -> String {
+    match location {
+        Some(l) => l.to_string(),
+        None => "unknown record".to_string(),
+    }
+}
+
+impl fmt::Display for DuplicateGroup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let locations: Vec<String> = self.locations.iter().map(describe).collect();
+        writeln!(
+            f,
+            "{} {} appears in {}",
+            self.identity,
+            self.key,
+            locations.join(", ")
+        )?;
+        for conflict in self.conflicts.iter() {
+            let values: Vec<String> = conflict
+                .values
+                .iter()
+                .map(|(l, v)| format!("{} in {}", v, describe(l)))
+                .collect();
+            writeln!(f, "  conflicting {}: {}", conflict.field, values.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::identity::resolve;
+    use crate::passport::{Location, Passport};
+
+    fn passport(record: usize, fields: &[(&str, &str)]) -> Passport {
+        let mut p = Passport::new();
+        p.location = Some(Location {
+            record,
+            first_line: record,
+            last_line: record,
+        });
+        for (name, value) in fields {
+            p.add_field(name, value.to_string());
+        }
+        p
+    }
+
+    #[test]
+    fn test_resolve() {
+        let passports = vec![
+            passport(1, &[("pid", "000000001"), ("byr", "1980")]),
+            passport(2, &[("pid", "000000002"), ("byr", "1990")]),
+            passport(3, &[("pid", "000000001"), ("byr", "1981")]),
+            passport(4, &[("byr", "1970")]),
+            passport(5, &[("pid", "000000002"), ("hgt", "170cm")]),
+            passport(6, &[("pid", "000000003")]),
+        ];
+
+        let resolution = resolve(passports, "pid");
+
+        let records: Vec<usize> = resolution
+            .passports
+            .iter()
+            .map(|p| p.location.unwrap().record)
+            .collect();
+        assert_eq!(vec![2, 4, 6], records);
+        assert_eq!("170cm", resolution.passports[0].fields["hgt"]);
+        assert_eq!("1990", resolution.passports[0].fields["byr"]);
+
+        assert_eq!(2, resolution.duplicates.len());
+        let conflicting = &resolution.duplicates[0];
+        assert_eq!("000000001", conflicting.key);
+        assert_eq!(1, conflicting.conflicts.len());
+        assert_eq!("byr", conflicting.conflicts[0].field);
+        assert_eq!(
+            concat!(
+                "pid 000000001 appears in Passport 1 (lines 1-1), Passport 3 (lines 3-3)\n",
+                "  conflicting byr: 1980 in Passport 1 (lines 1-1), 1981 in Passport 3 (lines 3-3)\n",
+            ),
+            conflicting.to_string()
+        );
+        assert!(resolution.duplicates[1].conflicts.is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+/// Rewrites a field value into its canonical form. Values the normaliser
+/// doesn't understand are left alone for the validator to reject.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Normaliser {
+    Lowercase,
+    ZeroPad {
+        width: usize,
+    },
+    ConvertUnit {
+        to: String,
+        factors: BTreeMap<String, f64>,
+    },
+}
+
+impl Normaliser {
+    pub fn apply(&self, value: &str) -> String {
+        match self {
+            Normaliser::Lowercase => value.to_lowercase(),
+            Normaliser::ZeroPad { width } => {
+                if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+                    format!("{:0>width$}", value, width = width)
+                } else {
+                    value.to_string()
+                }
+            }
+            Normaliser::ConvertUnit { to, factors } => factors
+                .iter()
+                .find_map(|(unit, factor)| {
+                    let number = value.strip_suffix(unit.as_str())?;
+                    if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit() || b == b'.')
+                    {
+                        return None;
+                    }
+                    let number: f64 = number.parse().ok()?;
+                    Some(format!("{}{}", (number * factor).round(), to))
+                })
+                .unwrap_or_else(|| value.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::normalise::Normaliser;
+
+    #[test]
+    fn test_normalisers() {
+        assert_eq!("#abcdef", Normaliser::Lowercase.apply("#ABCdef"));
+
+        let pad = Normaliser::ZeroPad { width: 9 };
+        assert_eq!("000012345", pad.apply("12345"));
+        assert_eq!("123456789", pad.apply("123456789"));
+        assert_eq!("0123456789", pad.apply("0123456789"));
+        assert_eq!("12a", pad.apply("12a"));
+        assert_eq!("", pad.apply(""));
+
+        let mut factors = BTreeMap::new();
+        factors.insert("in".to_string(), 2.54);
+        let to_cm = Normaliser::ConvertUnit {
+            to: "cm".to_string(),
+            factors,
+        };
+        assert_eq!("152cm", to_cm.apply("60in"));
+        assert_eq!("150cm", to_cm.apply("59in"));
+        assert_eq!("193cm", to_cm.apply("76in"));
+        assert_eq!("183cm", to_cm.apply("183cm"));
+        assert_eq!("tallin", to_cm.apply("tallin"));
+        assert_eq!("in", to_cm.apply("in"));
+        assert_eq!("infin", to_cm.apply("infin"));
+    }
+}
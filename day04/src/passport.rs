@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::schema::Schema;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Location {
+    pub record: usize,
+    pub first_line: usize,
+    pub last_line: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Passport {} (lines {}-{})",
+            self.record, self.first_line, self.last_line
+        )
+    }
+}
+
+pub struct Passport {
+    pub fields: BTreeMap<String, String>,
+    pub location: Option<Location>,
+}
+
+impl Passport {
+    pub fn new() -> Passport {
+        Passport {
+            fields: BTreeMap::new(),
+            location: None,
+        }
+    }
+
+    pub fn add_field(&mut self, name: &str, value: String) {
+        self.fields.insert(name.to_string(), value);
+    }
+
+    pub fn normalise(&mut self, schema: &Schema) {
+        for (name, value) in self.fields.iter_mut() {
+            *value = schema.normalise_field(name, value);
+        }
+    }
+
+    pub fn validate(&self, schema: &Schema) -> ValidationReport {
+        let missing = schema
+            .fields()
+            .iter()
+            .filter(|f| f.required && !self.fields.contains_key(&f.name))
+            .map(|f| f.name.clone())
+            .collect();
+
+        let mut invalid: Vec<FieldError> = schema
+            .fields()
+            .iter()
+            .filter_map(|f| {
+                let value = self.fields.get(&f.name)?;
+                schema
+                    .check_field(&f.name, value)
+                    .err()
+                    .map(|reason| FieldError {
+                        field: f.name.clone(),
+                        reason,
+                    })
+            })
+            .collect();
+        invalid.sort_by(|a, b| a.field.cmp(&b.field));
+
+        ValidationReport {
+            location: self.location,
+            missing,
+            invalid,
+            unknown: self
+                .unknown_fields(schema)
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        }
+    }
+
+    pub fn is_valid(&self, schema: &Schema) -> bool {
+        self.validate(schema).is_valid()
+    }
+
+    pub fn unknown_fields(&self, schema: &Schema) -> Vec<&str> {
+        self.fields
+            .keys()
+            .filter(|name| schema.field(name).is_none())
+            .map(|name| name.as_str())
+            .collect()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub reason: String,
+}
+
+/// Everything wrong with a single passport. Unknown fields are reported
+/// but do not make a passport invalid.
+#[derive(Debug)]
+pub struct ValidationReport {
+    pub location: Option<Location>,
+    pub missing: Vec<String>,
+    pub invalid: Vec<FieldError>,
+    pub unknown: Vec<String>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.missing.is_empty() && self.invalid.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Some(l) => write!(f, "{}", l)?,
+            None => write!(f, "Passport")?,
+        }
+        writeln!(
+            f,
+            " is {}",
+            if self.is_valid() { "valid" } else { "invalid" }
+        )?;
+        for name in self.missing.iter() {
+            writeln!(f, "  missing required field {}", name)?;
+        }
+        for e in self.invalid.iter() {
+            writeln!(f, "  {} {}", e.field, e.reason)?;
+        }
+        for name in self.unknown.iter() {
+            writeln!(f, "  unknown field {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::passport::{FieldError, Location, Passport};
+    use crate::schema::Schema;
+
+    #[test]
+    fn test_is_valid() {
+        let schema = Schema::builtin();
+
+        // all 8
+        let mut all8_passport = Passport::new();
+        all8_passport.add_field("byr", "2002".to_string());
+        all8_passport.add_field("iyr", "2012".to_string());
+        all8_passport.add_field("eyr", "2020".to_string());
+        all8_passport.add_field("hgt", "165cm".to_string());
+        all8_passport.add_field("hcl", "#123abc".to_string());
+        all8_passport.add_field("ecl", "brn".to_string());
+        all8_passport.add_field("pid", "000000001".to_string());
+        all8_passport.add_field("cid", "somewhere".to_string());
+        assert!(all8_passport.is_valid(&schema));
+
+        // all reqd fields (missing cid)
+        let mut all_reqd_passport = Passport::new();
+        all_reqd_passport.add_field("byr", "2002".to_string());
+        all_reqd_passport.add_field("iyr", "2012".to_string());
+        all_reqd_passport.add_field("eyr", "2020".to_string());
+        all_reqd_passport.add_field("hgt", "165cm".to_string());
+        all_reqd_passport.add_field("hcl", "#123abc".to_string());
+        all_reqd_passport.add_field("ecl", "brn".to_string());
+        all_reqd_passport.add_field("pid", "000000001".to_string());
+        assert!(all_reqd_passport.is_valid(&schema));
+    }
+
+    #[test]
+    fn test_unknown_fields() {
+        let schema = Schema::builtin();
+
+        let mut passport = Passport::new();
+        passport.add_field("byr", "2002".to_string());
+        passport.add_field("iyr", "2012".to_string());
+        passport.add_field("eyr", "2020".to_string());
+        passport.add_field("hgt", "165cm".to_string());
+        passport.add_field("hcl", "#123abc".to_string());
+        passport.add_field("ecl", "brn".to_string());
+        passport.add_field("pid", "000000001".to_string());
+        passport.add_field("zzz", "1".to_string());
+        passport.add_field("nat", "uk".to_string());
+        assert_eq!(vec!["nat", "zzz"], passport.unknown_fields(&schema));
+        assert!(passport.is_valid(&schema));
+    }
+
+    #[test]
+    fn test_normalise() {
+        let schema = Schema::builtin();
+
+        let mut passport = Passport::new();
+        passport.add_field("hgt", "60in".to_string());
+        passport.add_field("hcl", "#ABCDEF".to_string());
+        passport.add_field("pid", "1234".to_string());
+        passport.add_field("byr", "1980".to_string());
+        passport.add_field("nat", "UK".to_string());
+        passport.normalise(&schema);
+
+        assert_eq!("152cm", passport.fields["hgt"]);
+        assert_eq!("#abcdef", passport.fields["hcl"]);
+        assert_eq!("000001234", passport.fields["pid"]);
+        assert_eq!("1980", passport.fields["byr"]);
+        assert_eq!("UK", passport.fields["nat"]);
+    }
+
+    #[test]
+    fn test_validation_report() {
+        let schema = Schema::builtin();
+
+        let mut passport = Passport::new();
+        passport.location = Some(Location {
+            record: 3,
+            first_line: 7,
+            last_line: 9,
+        });
+        passport.add_field("byr", "2002".to_string());
+        passport.add_field("iyr", "2012".to_string());
+        passport.add_field("hgt", "190in".to_string());
+        passport.add_field("hcl", "#123abc".to_string());
+        passport.add_field("ecl", "wat".to_string());
+        passport.add_field("pid", "000000001".to_string());
+        passport.add_field("nat", "uk".to_string());
+
+        let report = passport.validate(&schema);
+        assert!(!report.is_valid());
+        assert_eq!(vec!["eyr".to_string()], report.missing);
+        assert_eq!(
+            vec![
+                FieldError {
+                    field: "ecl".to_string(),
+                    reason: "'wat' is not one of amb, blu, brn, gry, grn, hzl, oth".to_string(),
+                },
+                FieldError {
+                    field: "hgt".to_string(),
+                    reason: "'190in' exceeds 76in".to_string(),
+                },
+            ],
+            report.invalid
+        );
+        assert_eq!(vec!["nat".to_string()], report.unknown);
+        assert_eq!(
+            concat!(
+                "Passport 3 (lines 7-9) is invalid\n",
+                "  missing required field eyr\n",
+                "  ecl 'wat' is not one of amb, blu, brn, gry, grn, hzl, oth\n",
+                "  hgt '190in' exceeds 76in\n",
+                "  unknown field nat\n",
+            ),
+            report.to_string()
+        );
+    }
+}
@@ -0,0 +1,461 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::normalise::Normaliser;
+
+const BUILTIN_SCHEMA: &str = include_str!("../schema.toml");
+
+#[derive(Deserialize)]
+struct SchemaFile {
+    identity: Option<String>,
+    field: Vec<FieldDef>,
+}
+
+#[derive(Deserialize)]
+struct FieldDef {
+    name: String,
+    #[serde(default)]
+    required: bool,
+    #[serde(default)]
+    validator: ValidatorDef,
+    normalise: Option<Normaliser>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ValidatorDef {
+    #[default]
+    Any,
+    IntRange(Range),
+    UnitRange {
+        units: BTreeMap<String, Range>,
+    },
+    Regex {
+        pattern: String,
+    },
+    Enum {
+        values: Vec<String>,
+    },
+}
+
+#[derive(Deserialize)]
+struct Range {
+    min: i64,
+    max: i64,
+    #[serde(default)]
+    digits: Option<usize>,
+}
+
+#[derive(Debug)]
+pub enum SchemaError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Regex { field: String, error: regex::Error },
+    DuplicateField(String),
+    UnknownIdentity(String),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaError::Io(e) => write!(f, "could not read schema: {}", e),
+            SchemaError::Parse(e) => write!(f, "invalid schema: {}", e),
+            SchemaError::Regex { field, error } => {
+                write!(f, "invalid pattern for field {}: {}", field, error)
+            }
+            SchemaError::DuplicateField(name) => write!(f, "field {} is declared twice", name),
+            SchemaError::UnknownIdentity(name) => {
+                write!(f, "identity field {} is not declared", name)
+            }
+        }
+    }
+}
+
+enum Validator {
+    Any,
+    IntRange(Range),
+    UnitRange(Vec<(String, Range)>),
+    Regex(Regex, String),
+    Enum(Vec<String>),
+}
+
+impl Validator {
+    fn from_def(name: &str, def: ValidatorDef) -> Result<Validator, SchemaError> {
+        Ok(match def {
+            ValidatorDef::Any => Validator::Any,
+            ValidatorDef::IntRange(range) => Validator::IntRange(range),
+            ValidatorDef::UnitRange { units } => Validator::UnitRange(units.into_iter().collect()),
+            // patterns must match the whole value, not just part of it
+            ValidatorDef::Regex { pattern } => match Regex::new(&format!("^(?:{})$", pattern)) {
+                Ok(re) => Validator::Regex(re, pattern),
+                Err(error) => {
+                    return Err(SchemaError::Regex {
+                        field: name.to_string(),
+                        error,
+                    })
+                }
+            },
+            ValidatorDef::Enum { values } => Validator::Enum(values),
+        })
+    }
+
+    fn check(&self, value: &str) -> Result<(), String> {
+        match self {
+            Validator::Any => Ok(()),
+            Validator::IntRange(range) => check_number(value, value, "", range),
+            Validator::UnitRange(units) => {
+                let longest_unit = units
+                    .iter()
+                    .filter_map(|(unit, range)| {
+                        value
+                            .strip_suffix(unit.as_str())
+                            .map(|number| (number, unit, range))
+                    })
+                    .max_by_key(|(_, unit, _)| unit.len());
+                match longest_unit {
+                    Some((number, unit, range)) => check_number(value, number, unit, range),
+                    None => Err(format!(
+                        "'{}' has no unit (expected {})",
+                        value,
+                        units
+                            .iter()
+                            .map(|(unit, _)| unit.as_str())
+                            .collect::<Vec<&str>>()
+                            .join(" or ")
+                    )),
+                }
+            }
+            Validator::Regex(re, pattern) => {
+                if re.is_match(value) {
+                    Ok(())
+                } else {
+                    Err(format!("'{}' does not match {}", value, pattern))
+                }
+            }
+            Validator::Enum(values) => {
+                if values.iter().any(|v| v == value) {
+                    Ok(())
+                } else {
+                    Err(format!("'{}' is not one of {}", value, values.join(", ")))
+                }
+            }
+        }
+    }
+}
+
+fn check_number(value: &str, number: &str, unit: &str, range: &Range) -> Result<(), String> {
+    let magnitude = number.strip_prefix('-').unwrap_or(number);
+    if magnitude.is_empty() || !magnitude.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("'{}' is not a number", value));
+    }
+    if let Some(digits) = range.digits {
+        if magnitude.len() != digits {
+            return Err(format!("'{}' must have {} digits", value, digits));
+        }
+    }
+    match number.parse::<i64>() {
+        Ok(n) if n > range.max => Err(format!("'{}' exceeds {}{}", value, range.max, unit)),
+        Ok(n) if n < range.min => Err(format!("'{}' is below {}{}", value, range.min, unit)),
+        Ok(_) => Ok(()),
+        // only overflow is left, since we've checked it's all digits
+        Err(_) if magnitude.len() < number.len() => {
+            Err(format!("'{}' is below {}{}", value, range.min, unit))
+        }
+        Err(_) => Err(format!("'{}' exceeds {}{}", value, range.max, unit)),
+    }
+}
+
+pub struct FieldSpec {
+    pub name: String,
+    pub required: bool,
+    validator: Validator,
+    normaliser: Option<Normaliser>,
+}
+
+/// The set of fields a passport may carry, and the rules each must satisfy.
+pub struct Schema {
+    identity: Option<String>,
+    fields: Vec<FieldSpec>,
+}
+
+impl Schema {
+    pub fn parse(s: &str) -> Result<Schema, SchemaError> {
+        let file: SchemaFile = toml::from_str(s).map_err(SchemaError::Parse)?;
+        let mut fields: Vec<FieldSpec> = Vec::new();
+        for def in file.field {
+            if fields.iter().any(|f| f.name == def.name) {
+                return Err(SchemaError::DuplicateField(def.name));
+            }
+            let validator = Validator::from_def(&def.name, def.validator)?;
+            fields.push(FieldSpec {
+                name: def.name,
+                required: def.required,
+                validator,
+                normaliser: def.normalise,
+            });
+        }
+        if let Some(identity) = file.identity.as_ref() {
+            if !fields.iter().any(|f| &f.name == identity) {
+                return Err(SchemaError::UnknownIdentity(identity.clone()));
+            }
+        }
+        Ok(Schema {
+            identity: file.identity,
+            fields,
+        })
+    }
+
+    pub fn load(filename: &str) -> Result<Schema, SchemaError> {
+        let contents = fs::read_to_string(filename).map_err(SchemaError::Io)?;
+        Schema::parse(contents.as_str())
+    }
+
+    pub fn builtin() -> Schema {
+        Schema::parse(BUILTIN_SCHEMA).unwrap()
+    }
+
+    /// The field that identifies the same person across records.
+    pub fn identity(&self) -> Option<&str> {
+        self.identity.as_deref()
+    }
+
+    pub fn fields(&self) -> &[FieldSpec] {
+        &self.fields
+    }
+
+    pub fn field(&self, name: &str) -> Option<&FieldSpec> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    pub fn normalise_field(&self, name: &str, value: &str) -> String {
+        match self.field(name).and_then(|f| f.normaliser.as_ref()) {
+            Some(normaliser) => normaliser.apply(value),
+            None => value.to_string(),
+        }
+    }
+
+    pub fn check_field(&self, name: &str, value: &str) -> Result<(), String> {
+        match self.field(name) {
+            Some(spec) => spec.validator.check(value),
+            None => Err(format!("'{}' is not a known field", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::schema::{Schema, SchemaError};
+
+    #[test]
+    fn test_parse_schema() {
+        let schema = Schema::parse(
+            r#"
+            [[field]]
+            name = "age"
+            required = true
+            validator = { type = "int_range", min = 18, max = 99 }
+
+            [[field]]
+            name = "size"
+            validator = { type = "unit_range", units = { kg = { min = 1, max = 5 } } }
+
+            [[field]]
+            name = "tier"
+            validator = { type = "enum", values = ["gold", "silver"] }
+
+            [[field]]
+            name = "note"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(4, schema.fields().len());
+        assert!(schema.field("age").unwrap().required);
+        assert!(!schema.field("size").unwrap().required);
+        assert!(schema.check_field("age", "18").is_ok());
+        assert!(schema.check_field("age", "100").is_err());
+        assert!(schema.check_field("size", "5kg").is_ok());
+        assert!(schema.check_field("size", "5lb").is_err());
+        assert!(schema.check_field("size", "g").is_err());
+        assert!(schema.check_field("tier", "gold").is_ok());
+        assert!(schema.check_field("tier", "bronze").is_err());
+        assert!(schema.check_field("note", "anything").is_ok());
+        assert!(schema.check_field("unknown", "anything").is_err());
+    }
+
+    #[test]
+    fn test_validation() {
+        let schema = Schema::builtin();
+        assert!(schema.check_field("byr", "2002").is_ok());
+        assert!(schema.check_field("byr", "2003").is_err());
+        assert!(schema.check_field("hgt", "60in").is_ok());
+        assert!(schema.check_field("hgt", "190cm").is_ok());
+        assert!(schema.check_field("hgt", "190in").is_err());
+        assert!(schema.check_field("hgt", "190").is_err());
+        assert!(schema.check_field("hcl", "#123abc").is_ok());
+        assert!(schema.check_field("hcl", "#123abz").is_err());
+        assert!(schema.check_field("hcl", "123abc").is_err());
+        assert!(schema.check_field("ecl", "brn").is_ok());
+        assert!(schema.check_field("ecl", "wat").is_err());
+        assert!(schema.check_field("pid", "000000001").is_ok());
+        assert!(schema.check_field("pid", "0123456789").is_err());
+    }
+
+    #[test]
+    fn test_check_reasons() {
+        let schema = Schema::builtin();
+        assert_eq!(Ok(()), schema.check_field("hgt", "76in"));
+        assert_eq!(
+            Err("'190in' exceeds 76in".to_string()),
+            schema.check_field("hgt", "190in")
+        );
+        assert_eq!(
+            Err("'149cm' is below 150cm".to_string()),
+            schema.check_field("hgt", "149cm")
+        );
+        assert_eq!(
+            Err("'190' has no unit (expected cm or in)".to_string()),
+            schema.check_field("hgt", "190")
+        );
+        assert_eq!(
+            Err("'xxcm' is not a number".to_string()),
+            schema.check_field("hgt", "xxcm")
+        );
+        assert_eq!(
+            Err("'2003' exceeds 2002".to_string()),
+            schema.check_field("byr", "2003")
+        );
+        assert_eq!(
+            Err("'02002' must have 4 digits".to_string()),
+            schema.check_field("byr", "02002")
+        );
+        assert_eq!(
+            Err("'99999999999999999999cm' exceeds 193cm".to_string()),
+            schema.check_field("hgt", "99999999999999999999cm")
+        );
+        assert_eq!(
+            Err("'#123abz' does not match #[0-9a-f]{6}".to_string()),
+            schema.check_field("hcl", "#123abz")
+        );
+        assert_eq!(
+            Err("'wat' is not one of amb, blu, brn, gry, grn, hzl, oth".to_string()),
+            schema.check_field("ecl", "wat")
+        );
+    }
+
+    #[test]
+    fn test_schema_errors() {
+        let bad_regex = Schema::parse(
+            r#"
+            [[field]]
+            name = "pid"
+            validator = { type = "regex", pattern = "[0-9" }
+            "#,
+        );
+        assert!(matches!(bad_regex, Err(SchemaError::Regex { .. })));
+
+        let duplicate = Schema::parse(
+            r#"
+            [[field]]
+            name = "pid"
+            [[field]]
+            name = "pid"
+            "#,
+        );
+        assert!(matches!(duplicate, Err(SchemaError::DuplicateField(_))));
+
+        let unknown_validator = Schema::parse(
+            r#"
+            [[field]]
+            name = "pid"
+            validator = { type = "luhn" }
+            "#,
+        );
+        assert!(matches!(unknown_validator, Err(SchemaError::Parse(_))));
+    }
+
+    #[test]
+    fn test_conformance() {
+        let schema = Schema::builtin();
+        let cases = [
+            ("byr", "1919", false),
+            ("byr", "1920", true),
+            ("byr", "2002", true),
+            ("byr", "2003", false),
+            ("byr", "02002", false),
+            ("byr", "+2002", false),
+            ("byr", "2002 ", false),
+            ("byr", "", false),
+            ("byr", "abcd", false),
+            ("iyr", "2009", false),
+            ("iyr", "2010", true),
+            ("iyr", "2020", true),
+            ("iyr", "2021", false),
+            ("eyr", "2019", false),
+            ("eyr", "2020", true),
+            ("eyr", "2030", true),
+            ("eyr", "2031", false),
+            ("hgt", "149cm", false),
+            ("hgt", "150cm", true),
+            ("hgt", "193cm", true),
+            ("hgt", "194cm", false),
+            ("hgt", "58in", false),
+            ("hgt", "59in", true),
+            ("hgt", "76in", true),
+            ("hgt", "77in", false),
+            ("hgt", "60", false),
+            ("hgt", "60IN", false),
+            ("hgt", "60 in", false),
+            ("hgt", "-60in", false),
+            ("hgt", "99999999999999999999cm", false),
+            ("hgt", "cm", false),
+            ("hgt", "in", false),
+            ("hgt", "m", false),
+            ("hgt", "", false),
+            ("hcl", "#000000", true),
+            ("hcl", "#ffffff", true),
+            ("hcl", "#abcde", false),
+            ("hcl", "#abcdef0", false),
+            ("hcl", "#abcdeg", false),
+            ("hcl", "#ABCDEF", false),
+            ("hcl", "x#abcdef", false),
+            ("hcl", "abcdef", false),
+            ("hcl", "", false),
+            ("ecl", "amb", true),
+            ("ecl", "blu", true),
+            ("ecl", "brn", true),
+            ("ecl", "gry", true),
+            ("ecl", "grn", true),
+            ("ecl", "hzl", true),
+            ("ecl", "oth", true),
+            ("ecl", "AMB", false),
+            ("ecl", "ambx", false),
+            ("ecl", "amb ", false),
+            ("ecl", "", false),
+            ("pid", "000000000", true),
+            ("pid", "123456789", true),
+            ("pid", "12345678", false),
+            ("pid", "0123456789", false),
+            ("pid", " 12345678", false),
+            ("pid", "12345678a", false),
+            ("pid", "", false),
+            ("cid", "", true),
+            ("cid", "anything", true),
+        ];
+
+        for (field, value, expected) in cases.iter() {
+            assert_eq!(
+                *expected,
+                schema.check_field(field, value).is_ok(),
+                "{} '{}'",
+                field,
+                value
+            );
+        }
+    }
+}
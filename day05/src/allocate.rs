@@ -0,0 +1,341 @@
+use crate::bsp::{Codec, Seat};
+use crate::seatmap::{SeatMap, SeatState};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Preference {
+    Window,
+    Aisle,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Group {
+    pub name: String,
+    pub size: u32,
+    pub preference: Option<Preference>,
+    pub same_row: bool,
+}
+
+impl Group {
+    /// Parses `<name> <size> [window|aisle] [same-row]`.
+    pub fn parse(line: &str) -> Result<Group, String> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 2 {
+            return Err(format!("expected a name and a size in '{}'", line));
+        }
+        let size: u32 = match parts[1].parse() {
+            Ok(size) if size > 0 => size,
+            _ => return Err(format!("invalid group size '{}'", parts[1])),
+        };
+        let mut group = Group {
+            name: parts[0].to_string(),
+            size,
+            preference: None,
+            same_row: false,
+        };
+        for option in parts[2..].iter() {
+            match *option {
+                "window" => group.preference = Some(Preference::Window),
+                "aisle" => group.preference = Some(Preference::Aisle),
+                "same-row" => group.same_row = true,
+                _ => return Err(format!("unknown option '{}'", option)),
+            }
+        }
+        Ok(group)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Assignment {
+    pub group: String,
+    pub seats: Vec<Seat>,
+    pub preference_met: bool,
+    pub together: bool,
+}
+
+pub struct Allocation {
+    pub assignments: Vec<Assignment>,
+    pub unplaced: Vec<String>,
+    pub free_runs: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Run {
+    row: u32,
+    start: u32,
+    len: u32,
+}
+
+// Seats are only adjacent if they're on the same side of the aisle, which
+// runs down the middle of the plane.
+struct Cabin {
+    columns: u32,
+    free: Vec<Vec<bool>>,
+}
+
+impl Cabin {
+    fn sections(&self) -> Vec<(u32, u32)> {
+        if self.columns > 1 {
+            vec![(0, self.columns / 2), (self.columns / 2, self.columns)]
+        } else {
+            vec![(0, self.columns)]
+        }
+    }
+
+    fn runs(&self) -> Vec<Run> {
+        let mut runs = Vec::new();
+        for (row, seats) in self.free.iter().enumerate() {
+            for (from, to) in self.sections() {
+                let mut start: Option<u32> = None;
+                for column in from..=to {
+                    let free = column < to && seats[column as usize];
+                    match (free, start) {
+                        (true, None) => start = Some(column),
+                        (false, Some(s)) => {
+                            runs.push(Run {
+                                row: row as u32,
+                                start: s,
+                                len: column - s,
+                            });
+                            start = None;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        runs
+    }
+
+    fn meets(&self, preference: Option<Preference>, start: u32, len: u32) -> bool {
+        let end = start + len;
+        match preference {
+            None => true,
+            Some(Preference::Window) => start == 0 || end == self.columns,
+            Some(Preference::Aisle) => {
+                self.columns > 1 && (end == self.columns / 2 || start == self.columns / 2)
+            }
+        }
+    }
+
+    fn take(&mut self, row: u32, start: u32, len: u32) -> Vec<Seat> {
+        (start..start + len)
+            .map(|column| {
+                self.free[row as usize][column as usize] = false;
+                Seat { row, column }
+            })
+            .collect()
+    }
+
+    // Best fit: the smallest run that holds the whole group, placed at one end
+    // so what's left of the run stays in one piece.
+    fn place_together(&mut self, group: &Group) -> Option<(Vec<Seat>, bool)> {
+        let mut best: Option<(bool, u32, u32, u32)> = None;
+        for run in self.runs().iter().filter(|r| r.len >= group.size) {
+            for &start in [run.start, run.start + run.len - group.size].iter() {
+                let met = self.meets(group.preference, start, group.size);
+                let candidate = (!met, run.len - group.size, run.row, start);
+                if best.is_none_or(|b| candidate < b) {
+                    best = Some(candidate);
+                }
+            }
+        }
+        let (unmet, _, row, start) = best?;
+        Some((self.take(row, start, group.size), !unmet))
+    }
+
+    // Fills the largest runs first so the group is in as few pieces as
+    // possible, optionally keeping everyone in one row.
+    fn place_split(&mut self, group: &Group) -> Option<Vec<Seat>> {
+        let mut runs = self.runs();
+        if group.same_row {
+            let free_in_row =
+                |row: u32| -> u32 { runs.iter().filter(|r| r.row == row).map(|r| r.len).sum() };
+            let row = (0..self.free.len() as u32)
+                .filter(|&row| free_in_row(row) >= group.size)
+                .min_by_key(|&row| free_in_row(row) - group.size)?;
+            runs.retain(|r| r.row == row);
+        } else if runs.iter().map(|r| r.len).sum::<u32>() < group.size {
+            return None;
+        }
+        runs.sort_by(|a, b| b.len.cmp(&a.len).then(a.row.cmp(&b.row)));
+
+        let mut seats = Vec::new();
+        for run in runs {
+            let needed = group.size - seats.len() as u32;
+            if needed == 0 {
+                break;
+            }
+            seats.extend(self.take(run.row, run.start, run.len.min(needed)));
+        }
+        Some(seats)
+    }
+}
+
+/// Assigns free seats to each group, biggest groups first. Groups are kept
+/// together in adjacent seats where possible, and only split up when no
+/// run of free seats is long enough. Only seats the map counts as missing
+/// are free; rows outside the scanned passes don't exist.
+pub fn allocate(map: &SeatMap, codec: &Codec, groups: &[Group]) -> Allocation {
+    let columns = codec.column.size();
+    let mut cabin = Cabin {
+        columns,
+        free: (0..codec.row.size())
+            .map(|row| {
+                (0..columns)
+                    .map(|column| {
+                        map.state(codec.seat_id(&Seat { row, column })) == SeatState::Missing
+                    })
+                    .collect()
+            })
+            .collect(),
+    };
+
+    let mut order: Vec<usize> = (0..groups.len()).collect();
+    order.sort_by(|&a, &b| groups[b].size.cmp(&groups[a].size));
+
+    let mut placed: Vec<Option<Assignment>> = groups.iter().map(|_| None).collect();
+    for idx in order {
+        let group = &groups[idx];
+        placed[idx] = match cabin.place_together(group) {
+            Some((seats, preference_met)) => Some(Assignment {
+                group: group.name.clone(),
+                seats,
+                preference_met,
+                together: true,
+            }),
+            None => cabin.place_split(group).map(|seats| Assignment {
+                group: group.name.clone(),
+                preference_met: group.preference.is_none(),
+                seats,
+                together: false,
+            }),
+        };
+    }
+
+    let mut allocation = Allocation {
+        assignments: Vec::new(),
+        unplaced: Vec::new(),
+        free_runs: cabin.runs().len(),
+    };
+    for (group, assignment) in groups.iter().zip(placed) {
+        match assignment {
+            Some(a) => allocation.assignments.push(a),
+            None => allocation.unplaced.push(group.name.clone()),
+        }
+    }
+    allocation
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::allocate::{allocate, Group, Preference};
+    use crate::bsp::{Axis, Codec, Seat};
+    use crate::seatmap::SeatMap;
+
+    // `rows` rows of 4 seats, two either side of the aisle
+    fn small_plane(rows: u32, occupied: &[&str]) -> (Codec, SeatMap) {
+        let codec = Codec::new(
+            Axis::new('F', 'B', rows.trailing_zeros()).unwrap(),
+            Axis::new('L', 'R', 2).unwrap(),
+        );
+        let mut map = SeatMap::new(codec).unwrap();
+        for pass in occupied {
+            map.occupy(&codec.decode(pass).unwrap());
+        }
+        (codec, map)
+    }
+
+    fn passes(codec: &Codec, seats: &[Seat]) -> Vec<String> {
+        seats.iter().map(|s| codec.encode(s).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_parse_group() {
+        assert_eq!(
+            Ok(Group {
+                name: "smith".to_string(),
+                size: 3,
+                preference: Some(Preference::Window),
+                same_row: true,
+            }),
+            Group::parse("smith 3 window same-row")
+        );
+        assert!(Group::parse("smith").is_err());
+        assert!(Group::parse("smith 0").is_err());
+        assert!(Group::parse("smith 2 middle").is_err());
+    }
+
+    #[test]
+    fn test_allocate() {
+        // row 0 is full, rows 1 and 2 have one seat taken, row 3 is empty
+        // and the first seat of row 4 is the last one there is
+        let (codec, map) = small_plane(
+            8,
+            &[
+                "FFFLL", "FFFLR", "FFFRL", "FFFRR", "FFBLL", "FBFRR", "BFFLL",
+            ],
+        );
+        let groups = vec![
+            Group::parse("pair 2").unwrap(),
+            Group::parse("solo 1 window").unwrap(),
+            Group::parse("trio 3 same-row").unwrap(),
+            Group::parse("aisle 1 aisle").unwrap(),
+        ];
+
+        let allocation = allocate(&map, &codec, &groups);
+        assert!(allocation.unplaced.is_empty());
+
+        // nowhere fits three together, so the trio gets the row with exactly
+        // three seats left, split by the aisle
+        let trio = &allocation.assignments[2];
+        assert_eq!("trio", trio.group);
+        assert!(!trio.together);
+        assert_eq!(vec!["FFBRL", "FFBRR", "FFBLR"], passes(&codec, &trio.seats));
+
+        // the pair takes an exact fit rather than breaking up row 3
+        let pair = &allocation.assignments[0];
+        assert!(pair.together);
+        assert_eq!(vec!["FBFLL", "FBFLR"], passes(&codec, &pair.seats));
+
+        let solo = &allocation.assignments[1];
+        assert!(solo.preference_met);
+        assert_eq!(vec!["FBBLL"], passes(&codec, &solo.seats));
+        let aisle = &allocation.assignments[3];
+        assert!(aisle.preference_met);
+        assert_eq!(vec!["FBFRL"], passes(&codec, &aisle.seats));
+
+        assert_eq!(2, allocation.free_runs);
+    }
+
+    #[test]
+    fn test_allocate_full_plane() {
+        // only the first and last seats are taken, leaving 14 free
+        let (codec, map) = small_plane(4, &["FFLL", "BBRR"]);
+        let groups: Vec<Group> = [4, 4, 3, 3, 1]
+            .iter()
+            .enumerate()
+            .map(|(i, size)| Group::parse(&format!("g{} {}", i, size)).unwrap())
+            .collect();
+        let allocation = allocate(&map, &codec, &groups);
+        assert_eq!(4, allocation.assignments.len());
+        assert_eq!(vec!["g4".to_string()], allocation.unplaced);
+        assert_eq!(0, allocation.free_runs);
+    }
+
+    #[test]
+    fn test_allocate_nonexistent_rows() {
+        // with no passes at all there are no seats to give out
+        let (codec, map) = small_plane(4, &[]);
+        let allocation = allocate(&map, &codec, &[Group::parse("fam 2").unwrap()]);
+        assert_eq!(vec!["fam".to_string()], allocation.unplaced);
+        assert_eq!(0, allocation.free_runs);
+
+        // the front row isn't on this aircraft, so the family can't sit there
+        let (codec, map) = small_plane(4, &["FBLL", "BBRR"]);
+        let allocation = allocate(&map, &codec, &[Group::parse("fam 2").unwrap()]);
+        let fam = &allocation.assignments[0];
+        assert!(fam.together);
+        assert_eq!(vec!["FBRL", "FBRR"], passes(&codec, &fam.seats));
+        assert_eq!(5, allocation.free_runs);
+    }
+}
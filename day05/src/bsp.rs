@@ -0,0 +1,278 @@
+use std::fmt;
+
+use crate::seatmap::MAX_SEAT_BITS;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Seat {
+    pub row: u32,
+    pub column: u32,
+}
+
+/// One dimension of the partition: `low` picks the lower half, `high` the
+/// upper half, and there are `bits` characters for it in a pass.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Axis {
+    pub low: char,
+    pub high: char,
+    pub bits: u32,
+}
+
+impl Axis {
+    pub fn new(low: char, high: char, bits: u32) -> Result<Axis, CodecError> {
+        if low == high {
+            return Err(CodecError::AmbiguousAlphabet(low));
+        }
+        if bits == 0 || bits > 16 {
+            return Err(CodecError::UnsupportedBits(bits));
+        }
+        Ok(Axis { low, high, bits })
+    }
+
+    pub fn size(&self) -> u32 {
+        1 << self.bits
+    }
+
+    fn decode(&self, chars: &[char], offset: usize) -> Result<u32, CodecError> {
+        chars.iter().enumerate().try_fold(0, |value, (idx, &c)| {
+            let bit = if c == self.low {
+                0
+            } else if c == self.high {
+                1
+            } else {
+                return Err(CodecError::InvalidChar {
+                    position: offset + idx,
+                    found: c,
+                });
+            };
+            Ok((value << 1) | bit)
+        })
+    }
+
+    fn encode(&self, value: u32, out: &mut String) {
+        for shift in (0..self.bits).rev() {
+            out.push(if (value >> shift) & 1 == 1 {
+                self.high
+            } else {
+                self.low
+            });
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CodecError {
+    AmbiguousAlphabet(char),
+    UnsupportedBits(u32),
+    WrongLength {
+        expected: usize,
+        found: usize,
+    },
+    InvalidChar {
+        position: usize,
+        found: char,
+    },
+    OutOfRange(u32),
+    /// More seats than a `SeatMap` will hold, given as the bits of a seat id.
+    TooManySeats(u32),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodecError::AmbiguousAlphabet(c) => write!(f, "'{}' can't mean both halves", c),
+            CodecError::UnsupportedBits(bits) => {
+                write!(f, "an axis needs between 1 and 16 bits, not {}", bits)
+            }
+            CodecError::WrongLength { expected, found } => {
+                write!(f, "expected {} characters but found {}", expected, found)
+            }
+            CodecError::InvalidChar { position, found } => {
+                write!(f, "unexpected '{}' at position {}", found, position)
+            }
+            CodecError::OutOfRange(value) => write!(f, "{} is outside the plane", value),
+            CodecError::TooManySeats(bits) => write!(
+                f,
+                "a seat map holds at most {} bits of seat id, not {}",
+                MAX_SEAT_BITS, bits
+            ),
+        }
+    }
+}
+
+/// Converts between boarding passes, seats and seat ids. A seat id is the
+/// row and column bits concatenated, so the standard codec gives
+/// `row * 8 + column`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Codec {
+    pub row: Axis,
+    pub column: Axis,
+}
+
+impl Codec {
+    pub fn new(row: Axis, column: Axis) -> Codec {
+        Codec { row, column }
+    }
+
+    pub fn standard() -> Codec {
+        Codec {
+            row: Axis {
+                low: 'F',
+                high: 'B',
+                bits: 7,
+            },
+            column: Axis {
+                low: 'L',
+                high: 'R',
+                bits: 3,
+            },
+        }
+    }
+
+    pub fn pass_len(&self) -> usize {
+        (self.row.bits + self.column.bits) as usize
+    }
+
+    pub fn seat_count(&self) -> u64 {
+        self.row.size() as u64 * self.column.size() as u64
+    }
+
+    pub fn decode(&self, pass: &str) -> Result<Seat, CodecError> {
+        let chars: Vec<char> = pass.chars().collect();
+        if chars.len() != self.pass_len() {
+            return Err(CodecError::WrongLength {
+                expected: self.pass_len(),
+                found: chars.len(),
+            });
+        }
+        let (row_chars, column_chars) = chars.split_at(self.row.bits as usize);
+        Ok(Seat {
+            row: self.row.decode(row_chars, 0)?,
+            column: self.column.decode(column_chars, row_chars.len())?,
+        })
+    }
+
+    pub fn encode(&self, seat: &Seat) -> Result<String, CodecError> {
+        if seat.row >= self.row.size() {
+            return Err(CodecError::OutOfRange(seat.row));
+        }
+        if seat.column >= self.column.size() {
+            return Err(CodecError::OutOfRange(seat.column));
+        }
+        let mut pass = String::with_capacity(self.pass_len());
+        self.row.encode(seat.row, &mut pass);
+        self.column.encode(seat.column, &mut pass);
+        Ok(pass)
+    }
+
+    pub fn seat_id(&self, seat: &Seat) -> u32 {
+        (seat.row << self.column.bits) | seat.column
+    }
+
+    pub fn seat_for_id(&self, id: u32) -> Result<Seat, CodecError> {
+        if id as u64 >= self.seat_count() {
+            return Err(CodecError::OutOfRange(id));
+        }
+        Ok(Seat {
+            row: id >> self.column.bits,
+            column: id & (self.column.size() - 1),
+        })
+    }
+
+    pub fn encode_id(&self, id: u32) -> Result<String, CodecError> {
+        self.encode(&self.seat_for_id(id)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bsp::{Axis, Codec, CodecError, Seat};
+
+    #[test]
+    fn test_standard_codec() {
+        let codec = Codec::standard();
+        let seat = codec.decode("FBFBBFFRLR").unwrap();
+        assert_eq!(Seat { row: 44, column: 5 }, seat);
+        assert_eq!(357, codec.seat_id(&seat));
+        assert_eq!("FBFBBFFRLR", codec.encode(&seat).unwrap());
+        assert_eq!("BBFFBBFRLL", codec.encode_id(820).unwrap());
+
+        assert_eq!(
+            Err(CodecError::WrongLength {
+                expected: 10,
+                found: 3
+            }),
+            codec.decode("FBF")
+        );
+        assert_eq!(
+            Err(CodecError::InvalidChar {
+                position: 8,
+                found: 'B'
+            }),
+            codec.decode("FBFBBFFRBR")
+        );
+        assert_eq!(
+            Err(CodecError::OutOfRange(128)),
+            codec.encode(&Seat {
+                row: 128,
+                column: 0
+            })
+        );
+        assert_eq!(Err(CodecError::OutOfRange(1024)), codec.seat_for_id(1024));
+    }
+
+    #[test]
+    fn test_axis_validation() {
+        assert_eq!(
+            Err(CodecError::AmbiguousAlphabet('x')),
+            Axis::new('x', 'x', 3)
+        );
+        assert_eq!(Err(CodecError::UnsupportedBits(0)), Axis::new('0', '1', 0));
+        assert_eq!(
+            Err(CodecError::UnsupportedBits(17)),
+            Axis::new('0', '1', 17)
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let codecs = [
+            Codec::standard(),
+            Codec::new(
+                Axis::new('0', '1', 1).unwrap(),
+                Axis::new('a', 'b', 1).unwrap(),
+            ),
+            Codec::new(
+                Axis::new('<', '>', 5).unwrap(),
+                Axis::new('L', 'R', 4).unwrap(),
+            ),
+            // the same alphabet on both axes is fine, position tells them apart
+            Codec::new(
+                Axis::new('L', 'R', 4).unwrap(),
+                Axis::new('L', 'R', 6).unwrap(),
+            ),
+        ];
+        for codec in codecs.iter() {
+            for id in 0..codec.seat_count() as u32 {
+                let pass = codec.encode_id(id).unwrap();
+                assert_eq!(codec.pass_len(), pass.chars().count());
+                let seat = codec.decode(&pass).unwrap();
+                assert_eq!(id, codec.seat_id(&seat));
+                assert_eq!(pass, codec.encode(&seat).unwrap());
+            }
+        }
+
+        // too many seats to enumerate, so walk a pseudo-random sample
+        let wide = Codec::new(
+            Axis::new('F', 'B', 16).unwrap(),
+            Axis::new('L', 'R', 16).unwrap(),
+        );
+        let mut id: u32 = 0x9e37_79b9;
+        for _ in 0..10_000 {
+            id ^= id << 13;
+            id ^= id >> 17;
+            id ^= id << 5;
+            let pass = wide.encode_id(id).unwrap();
+            assert_eq!(id, wide.seat_id(&wide.decode(&pass).unwrap()));
+        }
+    }
+}
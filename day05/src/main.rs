@@ -1,101 +1,184 @@
+mod allocate;
+mod bsp;
+mod seatmap;
+
 use std::env;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
-struct Seat {
-    row: u16,
-    column: u16,
-}
-
-impl Seat {
-    fn seat_id(&self) -> u16 {
-        self.row * 8 + self.column
-    }
-
-    fn parse(seat_specifier: &str) -> Seat {
-        let mut row_min = 0;
-        let mut row_max = 128;
-        let mut col_min = 0;
-        let mut col_max = 8;
+use allocate::{allocate, Allocation, Group};
+use bsp::{Axis, Codec};
+use seatmap::SeatMap;
 
-        let chars: Vec<char> = seat_specifier.chars().collect();
-
-        for idx in 0..7 {
-            let mid_point = row_min + (row_max - row_min) / 2;
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let row = take_axis(&mut args, "--rows");
+    let column = take_axis(&mut args, "--columns");
+    let standard = Codec::standard();
+    let codec = Codec::new(
+        row.unwrap_or(standard.row),
+        column.unwrap_or(standard.column),
+    );
 
-            match chars[idx] {
-                'F' => row_max = mid_point,
-                'B' => row_min = mid_point,
-                _ => panic!("invalid seat spec!"),
-            }
-            // println!("row: {}-{}", row_min, row_max);
-        }
+    if args.len() < 2 {
+        panic!("Specify file");
+    }
 
-        for idx in 7..10 {
-            let mid_point = col_min + (col_max - col_min) / 2;
-            match chars[idx] {
-                'L' => col_max = mid_point,
-                'R' => col_min = mid_point,
-                _ => panic!("invalid seat spec!"),
+    if args[1] == "encode" {
+        for id in args.iter().skip(2) {
+            let id: u32 = id.parse().unwrap();
+            match codec.encode_id(id) {
+                Ok(pass) => println!("{}: {}", id, pass),
+                Err(e) => println!("{}: {}", id, e),
             }
-            // println!("col: {}-{}", col_min, col_max);
-        }
-
-        Seat {
-            row: row_min,
-            column: col_min,
         }
+        return;
     }
-}
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        panic!("Specify file");
+    let mode = args[1].clone();
+    if mode == "map" || mode == "svg" || mode == "allocate" {
+        args.remove(1);
     }
+
     let file = File::open(args.get(1).unwrap()).unwrap();
     let mut max_id = 0;
-    let mut seen_seats = vec![false; 8 * 128];
+    let mut seat_map = match SeatMap::new(codec) {
+        Ok(seat_map) => seat_map,
+        Err(e) => panic!("can't map this plane: {}", e),
+    };
+
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        let seat = match codec.decode(line.as_str()) {
+            Ok(seat) => seat,
+            Err(e) => panic!("invalid seat spec {}: {}", line, e),
+        };
+        let seat_id = codec.seat_id(&seat);
+        if seat_id > max_id {
+            max_id = seat_id;
+        }
+        seat_map.occupy(&seat);
+    }
 
-    for r in BufReader::new(file).lines() {
-        if let Ok(line) = r {
-            let seat = Seat::parse(line.as_str());
-            if seat.seat_id() > max_id {
-                max_id = seat.seat_id();
-            }
-            seen_seats[seat.seat_id() as usize] = true;
+    match mode.as_str() {
+        "map" => {
+            print!("{}", seat_map.render_ascii());
+            println!();
+            print!("{}", seat_map.gaps());
+            return;
+        }
+        "svg" => {
+            print!("{}", seat_map.render_svg());
+            return;
         }
+        "allocate" => {
+            let groups_file = match args.get(2) {
+                Some(f) => File::open(f).unwrap(),
+                None => panic!("Specify a groups file to allocate"),
+            };
+            let groups: Vec<Group> = BufReader::new(groups_file)
+                .lines()
+                .map_while(Result::ok)
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| match Group::parse(&line) {
+                    Ok(group) => group,
+                    Err(e) => panic!("invalid group: {}", e),
+                })
+                .collect();
+            print_allocation(&allocate(&seat_map, &codec, &groups), &codec);
+            return;
+        }
+        _ => {}
     }
 
     println!("Max seat id: {}", max_id);
 
-    for i in 8..(8 * 127) {
-        if !seen_seats[i] && seen_seats[i - 1] && seen_seats[i + 1] {
+    let row_size = codec.column.size() as u64;
+    for i in row_size..codec.seat_count().saturating_sub(row_size) {
+        let i = i as u32;
+        if !seat_map.is_occupied(i) && seat_map.is_occupied(i - 1) && seat_map.is_occupied(i + 1) {
             println!("Missing seat id is: {}", i);
             break;
         }
     }
 }
 
+fn print_allocation(allocation: &Allocation, codec: &Codec) {
+    for assignment in allocation.assignments.iter() {
+        let passes: Vec<String> = assignment
+            .seats
+            .iter()
+            .map(|s| codec.encode(s).unwrap())
+            .collect();
+        let mut notes = Vec::new();
+        if !assignment.together {
+            notes.push("split up");
+        }
+        if !assignment.preference_met {
+            notes.push("preference not met");
+        }
+        if notes.is_empty() {
+            println!("{}: {}", assignment.group, passes.join(" "));
+        } else {
+            println!(
+                "{}: {} ({})",
+                assignment.group,
+                passes.join(" "),
+                notes.join(", ")
+            );
+        }
+    }
+    for name in allocation.unplaced.iter() {
+        println!("{}: no room", name);
+    }
+    println!("Runs of free seats left: {}", allocation.free_runs);
+}
+
+// axes are given as the low and high characters then the number of bits,
+// e.g. FB7
+fn take_axis(args: &mut Vec<String>, flag: &str) -> Option<Axis> {
+    let idx = args.iter().position(|a| a == flag)?;
+    if idx + 1 >= args.len() {
+        panic!("{} needs an axis such as FB7", flag);
+    }
+    let spec = args.remove(idx + 1);
+    args.remove(idx);
+
+    let mut chars = spec.chars();
+    let (low, high) = match (chars.next(), chars.next()) {
+        (Some(low), Some(high)) => (low, high),
+        _ => panic!("invalid axis {}", spec),
+    };
+    let bits: u32 = match chars.as_str().parse() {
+        Ok(bits) => bits,
+        Err(_) => panic!("invalid axis {}", spec),
+    };
+    match Axis::new(low, high, bits) {
+        Ok(axis) => Some(axis),
+        Err(e) => panic!("invalid axis {}: {}", spec, e),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::Seat;
+    use crate::bsp::Codec;
 
     #[test]
     fn test_parsing() {
-        let seat1 = Seat::parse("BFFFBBFRRR");
+        let codec = Codec::standard();
+
+        let seat1 = codec.decode("BFFFBBFRRR").unwrap();
         assert_eq!(70, seat1.row);
         assert_eq!(7, seat1.column);
-        assert_eq!(567, seat1.seat_id());
+        assert_eq!(567, codec.seat_id(&seat1));
 
-        let seat2 = Seat::parse("FFFBBBFRRR");
+        let seat2 = codec.decode("FFFBBBFRRR").unwrap();
         assert_eq!(14, seat2.row);
         assert_eq!(7, seat2.column);
-        assert_eq!(119, seat2.seat_id());
+        assert_eq!(119, codec.seat_id(&seat2));
 
-        let seat3 = Seat::parse("BBFFBBFRLL");
+        let seat3 = codec.decode("BBFFBBFRLL").unwrap();
         assert_eq!(102, seat3.row);
         assert_eq!(4, seat3.column);
-        assert_eq!(820, seat3.seat_id());
+        assert_eq!(820, codec.seat_id(&seat3));
     }
 }
@@ -0,0 +1,259 @@
+use std::fmt::{self, Write};
+
+use crate::bsp::{Codec, CodecError, Seat};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SeatState {
+    Occupied,
+    Missing,
+    // seats before the first or after the last scanned pass, which the
+    // puzzle says don't exist on this aircraft
+    Nonexistent,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct GapReport {
+    pub single_seats: Vec<u32>,
+    pub empty_runs: Vec<(u32, u32)>,
+    pub missing_front_rows: Vec<u32>,
+    pub missing_back_rows: Vec<u32>,
+}
+
+impl fmt::Display for GapReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let runs: Vec<String> = self
+            .empty_runs
+            .iter()
+            .map(|(first, last)| format!("{}-{}", first, last))
+            .collect();
+        writeln!(f, "Single missing seats: {:?}", self.single_seats)?;
+        writeln!(f, "Empty runs: [{}]", runs.join(", "))?;
+        writeln!(f, "Missing front rows: {:?}", self.missing_front_rows)?;
+        writeln!(f, "Missing back rows: {:?}", self.missing_back_rows)
+    }
+}
+
+/// Keeps the map, one byte a seat, to a size worth allocating. Passes
+/// with more bits than this can still be encoded and decoded.
+pub const MAX_SEAT_BITS: u32 = 24;
+
+pub struct SeatMap {
+    codec: Codec,
+    occupied: Vec<bool>,
+    bounds: Option<(u32, u32)>,
+}
+
+impl SeatMap {
+    pub fn new(codec: Codec) -> Result<SeatMap, CodecError> {
+        if codec.pass_len() as u32 > MAX_SEAT_BITS {
+            return Err(CodecError::TooManySeats(codec.pass_len() as u32));
+        }
+        Ok(SeatMap {
+            codec,
+            occupied: vec![false; codec.seat_count() as usize],
+            bounds: None,
+        })
+    }
+
+    pub fn occupy(&mut self, seat: &Seat) {
+        let id = self.codec.seat_id(seat);
+        self.occupied[id as usize] = true;
+        self.bounds = match self.bounds {
+            Some((first, last)) => Some((first.min(id), last.max(id))),
+            None => Some((id, id)),
+        };
+    }
+
+    pub fn is_occupied(&self, id: u32) -> bool {
+        self.occupied[id as usize]
+    }
+
+    pub fn state(&self, id: u32) -> SeatState {
+        if self.is_occupied(id) {
+            return SeatState::Occupied;
+        }
+        match self.bounds {
+            Some((first, last)) if id > first && id < last => SeatState::Missing,
+            _ => SeatState::Nonexistent,
+        }
+    }
+
+    pub fn gaps(&self) -> GapReport {
+        let mut report = GapReport {
+            single_seats: Vec::new(),
+            empty_runs: Vec::new(),
+            missing_front_rows: Vec::new(),
+            missing_back_rows: Vec::new(),
+        };
+        let (first, last) = match self.bounds {
+            Some(bounds) => bounds,
+            None => {
+                report.missing_front_rows = (0..self.codec.row.size()).collect();
+                return report;
+            }
+        };
+
+        let mut run_start: Option<u32> = None;
+        for id in first..=last {
+            match (self.is_occupied(id), run_start) {
+                (false, None) => run_start = Some(id),
+                (true, Some(start)) => {
+                    if start == id - 1 {
+                        report.single_seats.push(start);
+                    } else {
+                        report.empty_runs.push((start, id - 1));
+                    }
+                    run_start = None;
+                }
+                _ => {}
+            }
+        }
+
+        let row_of = |id: u32| self.codec.seat_for_id(id).unwrap().row;
+        report.missing_front_rows = (0..row_of(first)).collect();
+        report.missing_back_rows = (row_of(last) + 1..self.codec.row.size()).collect();
+        report
+    }
+
+    fn seat_id(&self, row: u32, column: u32) -> u32 {
+        self.codec.seat_id(&Seat { row, column })
+    }
+
+    /// One line per row, with `#` for occupied seats, `.` for missing ones
+    /// and `-` for seats that don't exist. The aisle is down the middle.
+    pub fn render_ascii(&self) -> String {
+        let columns = self.codec.column.size();
+        let label_width = (self.codec.row.size() - 1).to_string().len();
+        let mut out = String::new();
+        for row in 0..self.codec.row.size() {
+            write!(out, "{:>width$} ", row, width = label_width).unwrap();
+            for column in 0..columns {
+                if columns > 1 && column == columns / 2 {
+                    out.push(' ');
+                }
+                out.push(match self.state(self.seat_id(row, column)) {
+                    SeatState::Occupied => '#',
+                    SeatState::Missing => '.',
+                    SeatState::Nonexistent => '-',
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn render_svg(&self) -> String {
+        const SIZE: u32 = 10;
+        const PADDING: u32 = 2;
+        let columns = self.codec.column.size();
+        let rows = self.codec.row.size();
+        // leave a seat's width for the aisle
+        let width = (columns + 1) * (SIZE + PADDING);
+        let height = rows * (SIZE + PADDING);
+
+        let mut out = String::new();
+        writeln!(
+            out,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+            width, height
+        )
+        .unwrap();
+        for row in 0..rows {
+            for column in 0..columns {
+                let id = self.seat_id(row, column);
+                let (colour, label) = match self.state(id) {
+                    SeatState::Occupied => ("#4a90d9", "occupied"),
+                    SeatState::Missing => ("#e94e4e", "missing"),
+                    SeatState::Nonexistent => ("#dddddd", "nonexistent"),
+                };
+                let aisle = if column >= columns / 2 { 1 } else { 0 };
+                writeln!(
+                    out,
+                    "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"><title>row {} column {} id {}: {}</title></rect>",
+                    (column + aisle) * (SIZE + PADDING),
+                    row * (SIZE + PADDING),
+                    SIZE,
+                    SIZE,
+                    colour,
+                    row,
+                    column,
+                    id,
+                    label
+                )
+                .unwrap();
+            }
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bsp::{Axis, Codec, CodecError};
+    use crate::seatmap::{GapReport, SeatMap, SeatState};
+
+    // 4 rows of 4 seats
+    fn small_map(occupied: &[u32]) -> SeatMap {
+        let codec = Codec::new(
+            Axis::new('F', 'B', 2).unwrap(),
+            Axis::new('L', 'R', 2).unwrap(),
+        );
+        let mut map = SeatMap::new(codec).unwrap();
+        for &id in occupied {
+            map.occupy(&codec.seat_for_id(id).unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn test_gaps() {
+        let map = small_map(&[5, 6, 8, 11]);
+        assert_eq!(
+            GapReport {
+                single_seats: vec![7],
+                empty_runs: vec![(9, 10)],
+                missing_front_rows: vec![0],
+                missing_back_rows: vec![3],
+            },
+            map.gaps()
+        );
+        assert_eq!(SeatState::Nonexistent, map.state(4));
+        assert_eq!(SeatState::Occupied, map.state(5));
+        assert_eq!(SeatState::Missing, map.state(9));
+        assert_eq!(SeatState::Nonexistent, map.state(12));
+
+        assert_eq!(
+            concat!(
+                "Single missing seats: [7]\n",
+                "Empty runs: [9-10]\n",
+                "Missing front rows: [0]\n",
+                "Missing back rows: [3]\n",
+            ),
+            map.gaps().to_string()
+        );
+
+        let empty = small_map(&[]);
+        assert_eq!(vec![0, 1, 2, 3], empty.gaps().missing_front_rows);
+        assert_eq!(SeatState::Nonexistent, empty.state(0));
+
+        // 16 bits of rows and 16 of columns would need a 4 GiB map
+        let wide = Codec::new(
+            Axis::new('F', 'B', 16).unwrap(),
+            Axis::new('L', 'R', 16).unwrap(),
+        );
+        assert_eq!(Some(CodecError::TooManySeats(32)), SeatMap::new(wide).err());
+    }
+
+    #[test]
+    fn test_render() {
+        let map = small_map(&[5, 6, 8, 11]);
+        assert_eq!("0 -- --\n1 -# #.\n2 #. .#\n3 -- --\n", map.render_ascii());
+
+        let svg = map.render_svg();
+        assert!(svg.starts_with("<svg "));
+        assert_eq!(16, svg.matches("<rect ").count());
+        assert!(svg.contains("row 1 column 3 id 7: missing"));
+        assert!(svg.contains("row 1 column 1 id 5: occupied"));
+    }
+}
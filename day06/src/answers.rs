@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// The questions that can be answered, each identified by a single
+/// character. A question's position in the alphabet is its bit in an
+/// `AnswerSet`.
+#[derive(Debug)]
+pub struct Alphabet {
+    symbols: Vec<char>,
+    index: HashMap<char, usize>,
+}
+
+impl Alphabet {
+    /// Builds an alphabet from the distinct characters of `symbols`, in the
+    /// order they first appear. Whitespace is never a question.
+    pub fn new(symbols: &str) -> Alphabet {
+        let mut alphabet = Alphabet {
+            symbols: Vec::new(),
+            index: HashMap::new(),
+        };
+        for c in symbols.chars().filter(|c| !c.is_whitespace()) {
+            if !alphabet.index.contains_key(&c) {
+                alphabet.index.insert(c, alphabet.symbols.len());
+                alphabet.symbols.push(c);
+            }
+        }
+        alphabet
+    }
+
+    pub fn lowercase() -> Alphabet {
+        Alphabet::new("abcdefghijklmnopqrstuvwxyz")
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn index_of(&self, c: char) -> Option<usize> {
+        self.index.get(&c).copied()
+    }
+
+    pub fn symbols(&self) -> &[char] {
+        &self.symbols
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct UnknownAnswer(pub char);
+
+impl fmt::Display for UnknownAnswer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' isn't a question in the alphabet", self.0)
+    }
+}
+
+const WORD_BITS: usize = 64;
+
+/// A set of question indices, stored as a growable bitset.
+#[derive(Clone, Debug, Default)]
+pub struct AnswerSet {
+    words: Vec<u64>,
+}
+
+impl AnswerSet {
+    pub fn new() -> AnswerSet {
+        AnswerSet { words: Vec::new() }
+    }
+
+    /// Parses one person's answers, failing on the first character that
+    /// isn't in `alphabet`.
+    pub fn parse(answers: &str, alphabet: &Alphabet) -> Result<AnswerSet, UnknownAnswer> {
+        let mut set = AnswerSet::new();
+        for c in answers.chars().filter(|c| !c.is_whitespace()) {
+            set.insert(alphabet.index_of(c).ok_or(UnknownAnswer(c))?);
+        }
+        Ok(set)
+    }
+
+    pub fn insert(&mut self, idx: usize) {
+        let word = idx / WORD_BITS;
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << (idx % WORD_BITS);
+    }
+
+    pub fn len(&self) -> u32 {
+        self.words.iter().map(|w| w.count_ones()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+
+    fn combine(&self, other: &AnswerSet, op: impl Fn(u64, u64) -> u64) -> AnswerSet {
+        let len = self.words.len().max(other.words.len());
+        let word = |words: &[u64], i: usize| words.get(i).copied().unwrap_or(0);
+        AnswerSet {
+            words: (0..len)
+                .map(|i| op(word(&self.words, i), word(&other.words, i)))
+                .collect(),
+        }
+    }
+
+    pub fn union(&self, other: &AnswerSet) -> AnswerSet {
+        self.combine(other, |a, b| a | b)
+    }
+
+    pub fn intersection(&self, other: &AnswerSet) -> AnswerSet {
+        self.combine(other, |a, b| a & b)
+    }
+
+    pub fn difference(&self, other: &AnswerSet) -> AnswerSet {
+        self.combine(other, |a, b| a & !b)
+    }
+
+    pub fn symmetric_difference(&self, other: &AnswerSet) -> AnswerSet {
+        self.combine(other, |a, b| a ^ b)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &w)| {
+            (0..WORD_BITS)
+                .filter(move |bit| w & (1 << bit) != 0)
+                .map(move |bit| i * WORD_BITS + bit)
+        })
+    }
+}
+
+// Trailing zero words don't change which questions are in the set.
+impl PartialEq for AnswerSet {
+    fn eq(&self, other: &AnswerSet) -> bool {
+        self.symmetric_difference(other).is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::answers::{Alphabet, AnswerSet, UnknownAnswer};
+
+    #[test]
+    fn test_alphabet() {
+        let alphabet = Alphabet::new("xyz yx€");
+        assert_eq!(&['x', 'y', 'z', '€'], alphabet.symbols());
+        assert_eq!(Some(3), alphabet.index_of('€'));
+        assert_eq!(None, alphabet.index_of('a'));
+        assert_eq!(26, Alphabet::lowercase().len());
+
+        assert_eq!(
+            Err(UnknownAnswer('A')),
+            AnswerSet::parse("abA", &Alphabet::lowercase())
+        );
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let mut a = AnswerSet::new();
+        a.insert(1);
+        a.insert(70);
+        let mut b = AnswerSet::new();
+        b.insert(1);
+        b.insert(2);
+
+        assert_eq!(vec![1, 2, 70], a.union(&b).iter().collect::<Vec<_>>());
+        assert_eq!(vec![1], a.intersection(&b).iter().collect::<Vec<_>>());
+        assert_eq!(vec![70], a.difference(&b).iter().collect::<Vec<_>>());
+        assert_eq!(
+            vec![2, 70],
+            a.symmetric_difference(&b).iter().collect::<Vec<_>>()
+        );
+        assert!(a.difference(&a).is_empty());
+        assert_eq!(b, b.intersection(&a.union(&b)));
+    }
+}
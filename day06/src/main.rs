@@ -1,101 +1,332 @@
+extern crate records;
+
 use std::env;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::BufReader;
+use std::process;
+use std::rc::Rc;
+
+use records::records;
+
+mod answers;
+mod survey;
+
+use answers::{Alphabet, AnswerSet, UnknownAnswer};
+use survey::Survey;
 
 struct CurrentGroupAnswers {
-    anyone_answered: Vec<bool>,
-    everyone_answered: Vec<bool>,
+    alphabet: Rc<Alphabet>,
+    members: Vec<AnswerSet>,
 }
 
 impl CurrentGroupAnswers {
-    fn new() -> CurrentGroupAnswers {
+    fn new(alphabet: Rc<Alphabet>) -> CurrentGroupAnswers {
         CurrentGroupAnswers {
-            anyone_answered: vec![false; 26],
-            everyone_answered: vec![true; 26],
+            alphabet,
+            members: Vec::new(),
         }
     }
 
-    fn handle_answers(&mut self, answers: &str) {
-        let mut this_answer = vec![false; 26];
-        answers.chars().for_each(|c| {
-            let i = c as usize - 'a' as usize;
-            self.anyone_answered[i] = true;
-            this_answer[i] = true;
-        });
+    /// Adds one member's answers. Nothing is recorded if any of them isn't
+    /// in the alphabet.
+    fn handle_answers(&mut self, answers: &str) -> Result<(), UnknownAnswer> {
+        self.members
+            .push(AnswerSet::parse(answers, &self.alphabet)?);
+        Ok(())
+    }
 
-        for i in 0..this_answer.len() {
-            self.everyone_answered[i] &= this_answer[i];
+    fn anyone_answered(&self) -> AnswerSet {
+        self.members
+            .iter()
+            .fold(AnswerSet::new(), |acc, member| acc.union(member))
+    }
+
+    fn everyone_answered(&self) -> AnswerSet {
+        match self.members.split_first() {
+            Some((first, rest)) => rest
+                .iter()
+                .fold(first.clone(), |acc, member| acc.intersection(member)),
+            None => AnswerSet::new(),
         }
     }
 
+    // Questions seen once so far, minus those seen again, leaves the ones
+    // exactly one member answered.
+    fn exactly_one_answered(&self) -> AnswerSet {
+        let mut seen = AnswerSet::new();
+        let mut seen_again = AnswerSet::new();
+        for member in self.members.iter() {
+            seen_again = seen_again.union(&seen.intersection(member));
+            seen = seen.union(member);
+        }
+        seen.difference(&seen_again)
+    }
+
+    fn at_least_answered(&self, k: u32) -> AnswerSet {
+        let mut set = AnswerSet::new();
+        for (idx, &count) in self.question_counts().iter().enumerate() {
+            if count >= k {
+                set.insert(idx);
+            }
+        }
+        set
+    }
+
+    /// Questions answered by more than half of the group.
+    fn majority_answered(&self) -> AnswerSet {
+        self.at_least_answered(self.members.len() as u32 / 2 + 1)
+    }
+
+    /// How many members answered each question, indexed like the alphabet.
+    fn question_counts(&self) -> Vec<u32> {
+        let mut counts = vec![0; self.alphabet.len()];
+        for member in self.members.iter() {
+            for idx in member.iter() {
+                counts[idx] += 1;
+            }
+        }
+        counts
+    }
+
     fn anyone_answered_count(&self) -> u32 {
-        CurrentGroupAnswers::count_trues(&self.anyone_answered)
+        self.anyone_answered().len()
     }
 
     fn everyone_answered_count(&self) -> u32 {
-        CurrentGroupAnswers::count_trues(&self.everyone_answered)
+        self.everyone_answered().len()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Aggregation {
+    Anyone,
+    Everyone,
+    AtLeast(u32),
+    ExactlyOne,
+    Majority,
+    Counts,
+}
+
+impl Aggregation {
+    fn parse(s: &str) -> Result<Aggregation, String> {
+        match s {
+            "anyone" => Ok(Aggregation::Anyone),
+            "everyone" => Ok(Aggregation::Everyone),
+            "exactly-one" => Ok(Aggregation::ExactlyOne),
+            "majority" => Ok(Aggregation::Majority),
+            "counts" => Ok(Aggregation::Counts),
+            _ => match s.strip_prefix("at-least=").map(str::parse) {
+                Some(Ok(k)) if k > 0 => Ok(Aggregation::AtLeast(k)),
+                _ => Err(format!("unknown aggregation '{}'", s)),
+            },
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            Aggregation::Anyone => "anyone answered".to_string(),
+            Aggregation::Everyone => "everyone answered".to_string(),
+            Aggregation::AtLeast(k) => format!("at least {} answered", k),
+            Aggregation::ExactlyOne => "exactly one answered".to_string(),
+            Aggregation::Majority => "majority answered".to_string(),
+            Aggregation::Counts => "answers per question".to_string(),
+        }
     }
 
-    fn count_trues(v: &Vec<bool>) -> u32 {
-        v.iter()
-            .map(|&a| match a {
-                true => 1,
-                false => 0,
-            })
-            .sum()
+    fn count(&self, group: &CurrentGroupAnswers) -> u32 {
+        match self {
+            Aggregation::Anyone => group.anyone_answered_count(),
+            Aggregation::Everyone => group.everyone_answered_count(),
+            Aggregation::AtLeast(k) => group.at_least_answered(*k).len(),
+            Aggregation::ExactlyOne => group.exactly_one_answered().len(),
+            Aggregation::Majority => group.majority_answered().len(),
+            Aggregation::Counts => group.question_counts().iter().sum(),
+        }
+    }
+}
+
+fn take_option(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let idx = args.iter().position(|a| a == flag)?;
+    if idx + 1 >= args.len() {
+        panic!("{} needs a value", flag);
+    }
+    args.remove(idx);
+    Some(args.remove(idx))
+}
+
+/// Calls `f` with the first line of each group and its answers, exiting if
+/// the file can't be read or any answer isn't in the alphabet.
+fn for_each_group(
+    filename: &str,
+    alphabet: &Rc<Alphabet>,
+    mut f: impl FnMut(usize, &CurrentGroupAnswers),
+) {
+    let file = File::open(filename).unwrap();
+    for r in records(BufReader::new(file)) {
+        let record = match r {
+            Ok(record) => record,
+            Err(e) => {
+                eprintln!("Could not read {}: {}", filename, e);
+                process::exit(1);
+            }
+        };
+        let mut current_answers = CurrentGroupAnswers::new(Rc::clone(alphabet));
+        for (line_number, line) in (record.first_line..).zip(record.lines.iter()) {
+            if let Err(e) = current_answers.handle_answers(line.as_str()) {
+                eprintln!("Line {}: {}", line_number, e);
+                process::exit(1);
+            }
+        }
+        f(record.first_line, &current_answers);
+    }
+}
+
+fn survey(filename: &str, alphabet: &Rc<Alphabet>, json: bool, threshold: f64) {
+    let mut survey = Survey::new(Rc::clone(alphabet));
+    for_each_group(filename, alphabet, |first_line, group| {
+        survey.add_group(first_line, group)
+    });
+    let report = survey.report(threshold);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        print!("{}", report);
     }
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    let alphabet = Rc::new(match take_option(&mut args, "--alphabet") {
+        Some(symbols) => Alphabet::new(&symbols),
+        None => Alphabet::lowercase(),
+    });
     if args.len() < 2 {
         panic!("Please specify a file to work on");
     }
+
+    if args[1] == "survey" {
+        let threshold = match take_option(&mut args, "--outlier-threshold") {
+            Some(t) => t.parse().expect("--outlier-threshold needs a number"),
+            None => 2.0,
+        };
+        let json = match args.iter().position(|a| a == "--json") {
+            Some(idx) => {
+                args.remove(idx);
+                true
+            }
+            None => false,
+        };
+        let input_filename = args.get(2).expect("Please specify a file to survey");
+        survey(input_filename, &alphabet, json, threshold);
+        return;
+    }
+
     let input_filename = args.get(1).unwrap();
-    let file = File::open(input_filename).unwrap();
-
-    let mut total_anyone_answered = 0;
-    let mut total_everyone_answered = 0;
-    let mut current_answers = CurrentGroupAnswers::new();
-
-    for r in BufReader::new(file).lines() {
-        if let Ok(line) = r {
-            if line.len() == 0 {
-                total_anyone_answered += current_answers.anyone_answered_count();
-                total_everyone_answered += current_answers.everyone_answered_count();
-                current_answers = CurrentGroupAnswers::new();
-            } else {
-                current_answers.handle_answers(line.as_str());
+
+    let mut aggregations = Vec::new();
+    for arg in args[2..].iter() {
+        match Aggregation::parse(arg) {
+            Ok(aggregation) => aggregations.push(aggregation),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(2);
             }
         }
     }
-    total_anyone_answered += current_answers.anyone_answered_count();
-    total_everyone_answered += current_answers.everyone_answered_count();
+    if aggregations.is_empty() {
+        aggregations = vec![Aggregation::Anyone, Aggregation::Everyone];
+    }
+
+    let mut totals = vec![0; aggregations.len()];
+    let mut question_totals = vec![0; alphabet.len()];
 
-    println!("Total anyone answered: {}", total_anyone_answered);
-    println!("Total everyone answered: {}", total_everyone_answered);
+    for_each_group(input_filename, &alphabet, |_, current_answers| {
+        for (total, aggregation) in totals.iter_mut().zip(aggregations.iter()) {
+            *total += aggregation.count(current_answers);
+        }
+        for (total, count) in question_totals
+            .iter_mut()
+            .zip(current_answers.question_counts())
+        {
+            *total += count;
+        }
+    });
+
+    for (total, aggregation) in totals.iter().zip(aggregations.iter()) {
+        println!("Total {}: {}", aggregation.label(), total);
+        if *aggregation == Aggregation::Counts {
+            for (symbol, count) in alphabet.symbols().iter().zip(question_totals.iter()) {
+                println!("  {}: {}", symbol, count);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::CurrentGroupAnswers;
+    use std::rc::Rc;
+
+    use crate::answers::{Alphabet, UnknownAnswer};
+    use crate::{Aggregation, CurrentGroupAnswers};
 
     #[test]
     fn test_answer_counts() {
-        let mut a = CurrentGroupAnswers::new();
-        a.handle_answers("abc");
+        let mut a = CurrentGroupAnswers::new(Rc::new(Alphabet::lowercase()));
+        a.handle_answers("abc").unwrap();
         assert_eq!(3, a.anyone_answered_count());
         assert_eq!(3, a.everyone_answered_count());
 
-        let mut b = CurrentGroupAnswers::new();
-        b.handle_answers("a");
+        let mut b = CurrentGroupAnswers::new(Rc::new(Alphabet::lowercase()));
+        b.handle_answers("a").unwrap();
         assert_eq!(1, b.anyone_answered_count());
         assert_eq!(1, b.everyone_answered_count());
-        b.handle_answers("b");
+        b.handle_answers("b").unwrap();
         assert_eq!(2, b.anyone_answered_count());
         assert_eq!(0, b.everyone_answered_count());
-        b.handle_answers("c");
+        b.handle_answers("c").unwrap();
         assert_eq!(3, b.anyone_answered_count());
         assert_eq!(0, b.everyone_answered_count());
     }
+
+    #[test]
+    fn test_aggregations() {
+        let mut group = CurrentGroupAnswers::new(Rc::new(Alphabet::lowercase()));
+        for answers in ["abcx", "abd", "ab", "acy"].iter() {
+            group.handle_answers(answers).unwrap();
+        }
+        let letters = |set: crate::AnswerSet| -> String {
+            set.iter()
+                .map(|idx| group.alphabet.symbols()[idx])
+                .collect()
+        };
+        assert_eq!("abcdxy", letters(group.anyone_answered()));
+        assert_eq!("a", letters(group.everyone_answered()));
+        assert_eq!("dxy", letters(group.exactly_one_answered()));
+        assert_eq!("abc", letters(group.at_least_answered(2)));
+        assert_eq!("ab", letters(group.majority_answered()));
+        assert_eq!(4, group.question_counts()[0]);
+        assert_eq!(1, group.question_counts()[23]);
+
+        assert_eq!(Err(UnknownAnswer('!')), group.handle_answers("a!"));
+        assert_eq!(4, group.members.len());
+
+        assert_eq!(
+            Ok(Aggregation::AtLeast(3)),
+            Aggregation::parse("at-least=3")
+        );
+        assert!(Aggregation::parse("at-least=0").is_err());
+        assert!(Aggregation::parse("sometimes").is_err());
+    }
+
+    #[test]
+    fn test_custom_alphabet() {
+        let alphabet = Rc::new(Alphabet::new("αβγ!"));
+        let mut group = CurrentGroupAnswers::new(alphabet);
+        group.handle_answers("αβ!").unwrap();
+        group.handle_answers("β! ").unwrap();
+        assert_eq!(3, group.anyone_answered_count());
+        assert_eq!(2, group.everyone_answered_count());
+        assert_eq!(vec![1, 2, 0, 2], group.question_counts());
+        assert_eq!(Err(UnknownAnswer('a')), group.handle_answers("a"));
+    }
 }
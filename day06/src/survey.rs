@@ -0,0 +1,309 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+use serde::Serialize;
+
+use crate::answers::Alphabet;
+use crate::CurrentGroupAnswers;
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct QuestionStats {
+    pub question: char,
+    pub yes: u32,
+    /// The share of everyone surveyed who answered yes.
+    pub frequency: f64,
+    pub groups_anyone: u32,
+    pub groups_everyone: u32,
+}
+
+/// A group whose answers per member are unusually far from the average
+/// over all groups.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Outlier {
+    pub group: usize,
+    pub first_line: usize,
+    pub size: usize,
+    pub answers_per_member: f64,
+    pub z_score: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SurveyReport {
+    pub groups: usize,
+    pub people: usize,
+    pub questions: Vec<QuestionStats>,
+    pub group_sizes: BTreeMap<usize, usize>,
+    /// How many people answered yes to both questions, indexed like
+    /// `questions`.
+    pub co_occurrence: Vec<Vec<u32>>,
+    /// The phi coefficient between each pair of questions, or `None` when
+    /// one of them has the same answer from everyone.
+    pub correlation: Vec<Vec<Option<f64>>>,
+    pub outliers: Vec<Outlier>,
+}
+
+struct GroupSummary {
+    first_line: usize,
+    size: usize,
+    answers_per_member: f64,
+}
+
+/// Accumulates statistics one group at a time, so the input never has to
+/// be held in memory.
+pub struct Survey {
+    alphabet: Rc<Alphabet>,
+    groups: Vec<GroupSummary>,
+    people: usize,
+    yes: Vec<u32>,
+    groups_anyone: Vec<u32>,
+    groups_everyone: Vec<u32>,
+    co_occurrence: Vec<Vec<u32>>,
+}
+
+impl Survey {
+    pub fn new(alphabet: Rc<Alphabet>) -> Survey {
+        let n = alphabet.len();
+        Survey {
+            alphabet,
+            groups: Vec::new(),
+            people: 0,
+            yes: vec![0; n],
+            groups_anyone: vec![0; n],
+            groups_everyone: vec![0; n],
+            co_occurrence: vec![vec![0; n]; n],
+        }
+    }
+
+    pub fn add_group(&mut self, first_line: usize, group: &CurrentGroupAnswers) {
+        for (total, count) in self.yes.iter_mut().zip(group.question_counts()) {
+            *total += count;
+        }
+        for idx in group.anyone_answered().iter() {
+            self.groups_anyone[idx] += 1;
+        }
+        for idx in group.everyone_answered().iter() {
+            self.groups_everyone[idx] += 1;
+        }
+        for member in group.members.iter() {
+            let answered: Vec<usize> = member.iter().collect();
+            for &a in answered.iter() {
+                for &b in answered.iter() {
+                    self.co_occurrence[a][b] += 1;
+                }
+            }
+        }
+
+        let size = group.members.len();
+        let answers: u32 = group.members.iter().map(|m| m.len()).sum();
+        self.people += size;
+        self.groups.push(GroupSummary {
+            first_line,
+            size,
+            answers_per_member: answers as f64 / size.max(1) as f64,
+        });
+    }
+
+    fn correlation(&self, a: usize, b: usize) -> Option<f64> {
+        let n = self.people as f64;
+        let (yes_a, yes_b) = (self.yes[a] as f64, self.yes[b] as f64);
+        let both = self.co_occurrence[a][b] as f64;
+        let variance = yes_a * (n - yes_a) * yes_b * (n - yes_b);
+        if variance == 0.0 {
+            return None;
+        }
+        Some((n * both - yes_a * yes_b) / variance.sqrt())
+    }
+
+    /// Groups whose answers per member are more than `threshold` standard
+    /// deviations from the mean.
+    fn outliers(&self, threshold: f64) -> Vec<Outlier> {
+        let n = self.groups.len() as f64;
+        let mean = self
+            .groups
+            .iter()
+            .map(|g| g.answers_per_member)
+            .sum::<f64>()
+            / n;
+        let variance = self
+            .groups
+            .iter()
+            .map(|g| (g.answers_per_member - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        if variance == 0.0 {
+            return Vec::new();
+        }
+        self.groups
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, g)| {
+                let z_score = (g.answers_per_member - mean) / variance.sqrt();
+                if z_score.abs() > threshold {
+                    Some(Outlier {
+                        group: idx + 1,
+                        first_line: g.first_line,
+                        size: g.size,
+                        answers_per_member: g.answers_per_member,
+                        z_score,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn report(&self, outlier_threshold: f64) -> SurveyReport {
+        let n = self.alphabet.len();
+        let mut group_sizes = BTreeMap::new();
+        for group in self.groups.iter() {
+            *group_sizes.entry(group.size).or_insert(0) += 1;
+        }
+        SurveyReport {
+            groups: self.groups.len(),
+            people: self.people,
+            questions: (0..n)
+                .map(|idx| QuestionStats {
+                    question: self.alphabet.symbols()[idx],
+                    yes: self.yes[idx],
+                    frequency: self.yes[idx] as f64 / self.people.max(1) as f64,
+                    groups_anyone: self.groups_anyone[idx],
+                    groups_everyone: self.groups_everyone[idx],
+                })
+                .collect(),
+            group_sizes,
+            co_occurrence: self.co_occurrence.clone(),
+            correlation: (0..n)
+                .map(|a| (0..n).map(|b| self.correlation(a, b)).collect())
+                .collect(),
+            outliers: self.outliers(outlier_threshold),
+        }
+    }
+}
+
+impl fmt::Display for SurveyReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} groups, {} people", self.groups, self.people)?;
+
+        writeln!(f, "\nQuestion   Yes  Freq  Anyone  Everyone")?;
+        for q in self.questions.iter() {
+            writeln!(
+                f,
+                "{:<8} {:>5} {:>5.2} {:>7} {:>9}",
+                q.question, q.yes, q.frequency, q.groups_anyone, q.groups_everyone
+            )?;
+        }
+
+        writeln!(f, "\nGroup size  Groups")?;
+        for (size, count) in self.group_sizes.iter() {
+            writeln!(f, "{:>10} {:>7}", size, count)?;
+        }
+
+        // Questions nobody answered only add empty rows and columns.
+        let answered: Vec<usize> = (0..self.questions.len())
+            .filter(|&idx| self.questions[idx].yes > 0)
+            .collect();
+        let header: String = answered
+            .iter()
+            .map(|&idx| format!("{:>6}", self.questions[idx].question))
+            .collect();
+
+        writeln!(f, "\nCo-occurrence\n {}", header)?;
+        for &a in answered.iter() {
+            write!(f, "{}", self.questions[a].question)?;
+            for &b in answered.iter() {
+                write!(f, "{:>6}", self.co_occurrence[a][b])?;
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f, "\nCorrelation\n {}", header)?;
+        for &a in answered.iter() {
+            write!(f, "{}", self.questions[a].question)?;
+            for &b in answered.iter() {
+                match self.correlation[a][b] {
+                    Some(phi) => write!(f, "{:>6.2}", phi)?,
+                    None => write!(f, "{:>6}", "-")?,
+                }
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f, "\nOutliers")?;
+        if self.outliers.is_empty() {
+            writeln!(f, "none")?;
+        }
+        for o in self.outliers.iter() {
+            writeln!(
+                f,
+                "Group {} (line {}): {} members, {:.2} answers each, z = {:.2}",
+                o.group, o.first_line, o.size, o.answers_per_member, o.z_score
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::answers::Alphabet;
+    use crate::survey::Survey;
+    use crate::CurrentGroupAnswers;
+
+    fn survey(groups: &[&[&str]]) -> Survey {
+        let alphabet = Rc::new(Alphabet::new("abc"));
+        let mut survey = Survey::new(Rc::clone(&alphabet));
+        for (idx, members) in groups.iter().enumerate() {
+            let mut group = CurrentGroupAnswers::new(Rc::clone(&alphabet));
+            for answers in members.iter() {
+                group.handle_answers(answers).unwrap();
+            }
+            survey.add_group(idx * 10 + 1, &group);
+        }
+        survey
+    }
+
+    #[test]
+    fn test_report() {
+        let report = survey(&[&["ab", "a"], &["ab"], &["a", "a", "a"], &["abc"]]).report(1.5);
+
+        assert_eq!(4, report.groups);
+        assert_eq!(7, report.people);
+        let a = &report.questions[0];
+        assert_eq!(
+            (7, 1.0, 4, 4),
+            (a.yes, a.frequency, a.groups_anyone, a.groups_everyone)
+        );
+        let b = &report.questions[1];
+        assert_eq!((3, 3, 2), (b.yes, b.groups_anyone, b.groups_everyone));
+        assert_eq!(
+            vec![(1, 2), (2, 1), (3, 1)],
+            report.group_sizes.into_iter().collect::<Vec<_>>()
+        );
+
+        assert_eq!(vec![7, 3, 1], report.co_occurrence[0]);
+        assert_eq!(vec![3, 3, 1], report.co_occurrence[1]);
+        // everyone answered a, so it can't correlate with anything
+        assert_eq!(None, report.correlation[0][1]);
+        assert_eq!(Some(1.0), report.correlation[1][1]);
+        let phi = report.correlation[1][2].unwrap();
+        assert!((phi - (7.0 - 3.0) / (3.0f64 * 4.0 * 6.0).sqrt()).abs() < 1e-9);
+
+        assert_eq!(1, report.outliers.len());
+        assert_eq!(4, report.outliers[0].group);
+        assert_eq!(31, report.outliers[0].first_line);
+        assert_eq!(3.0, report.outliers[0].answers_per_member);
+    }
+
+    #[test]
+    fn test_uniform_groups_have_no_outliers() {
+        let report = survey(&[&["a"], &["b"], &["c"]]).report(0.5);
+        assert!(report.outliers.is_empty());
+        let table = report.to_string();
+        assert!(table.starts_with("3 groups, 3 people\n"));
+        assert!(table.ends_with("Outliers\nnone\n"));
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+use crate::graph::{BagGraph, GraphError};
+use crate::Rule;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Dot,
+    Mermaid,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Format> {
+        match s {
+            "dot" => Some(Format::Dot),
+            "mermaid" => Some(Format::Mermaid),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ExportOptions {
+    /// Highlight every bag that can hold this colour.
+    pub ancestors_of: Option<String>,
+    /// Highlight every bag this colour has to hold.
+    pub descendants_of: Option<String>,
+    /// Only export this colour and the bags it's connected to by
+    /// containment, in either direction.
+    pub around: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Style {
+    Plain,
+    Focus,
+    Ancestor,
+    Descendant,
+}
+
+impl Style {
+    fn fill(&self) -> &'static str {
+        match self {
+            Style::Plain => "#ffffff",
+            Style::Focus => "#ffd700",
+            Style::Ancestor => "#add8e6",
+            Style::Descendant => "#90ee90",
+        }
+    }
+
+    fn class(&self) -> &'static str {
+        match self {
+            Style::Plain => "plain",
+            Style::Focus => "focus",
+            Style::Ancestor => "ancestor",
+            Style::Descendant => "descendant",
+        }
+    }
+}
+
+struct Edge<'a> {
+    from: &'a str,
+    to: &'a str,
+    quantity: u32,
+}
+
+/// Renders the rules as a DOT or Mermaid graph, with an edge from each bag
+/// to the bags it holds labelled with how many.
+pub fn export(
+    rules: &[Rule],
+    graph: &BagGraph,
+    format: Format,
+    options: &ExportOptions,
+) -> Result<String, GraphError> {
+    let mut colours: Vec<&str> = Vec::new();
+    let mut seen: BTreeSet<&str> = BTreeSet::new();
+    let mut edges = Vec::new();
+    for rule in rules {
+        for colour in Some(rule.colour.as_str())
+            .into_iter()
+            .chain(rule.allowed_contents.iter().map(|c| c.colour.as_str()))
+        {
+            if seen.insert(colour) {
+                colours.push(colour);
+            }
+        }
+        for constraint in rule.allowed_contents.iter() {
+            edges.push(Edge {
+                from: &rule.colour,
+                to: &constraint.colour,
+                quantity: constraint.quantity,
+            });
+        }
+    }
+
+    if let Some(centre) = &options.around {
+        let mut keep = graph.ancestors(centre)?;
+        keep.extend(graph.descendants(centre)?);
+        keep.insert(centre);
+        colours.retain(|c| keep.contains(c));
+        edges.retain(|e| keep.contains(e.from) && keep.contains(e.to));
+    }
+
+    let mut styles: HashMap<&str, Style> = HashMap::new();
+    if let Some(colour) = &options.descendants_of {
+        for c in graph.descendants(colour)? {
+            styles.insert(c, Style::Descendant);
+        }
+        styles.insert(colour, Style::Focus);
+    }
+    if let Some(colour) = &options.ancestors_of {
+        for c in graph.ancestors(colour)? {
+            styles.insert(c, Style::Ancestor);
+        }
+        styles.insert(colour, Style::Focus);
+    }
+    let style = |colour: &str| styles.get(colour).copied().unwrap_or(Style::Plain);
+
+    Ok(match format {
+        Format::Dot => to_dot(&colours, &edges, style),
+        Format::Mermaid => to_mermaid(&colours, &edges, style),
+    })
+}
+
+fn to_dot(colours: &[&str], edges: &[Edge], style: impl Fn(&str) -> Style) -> String {
+    let mut out = String::from("digraph bags {\n");
+    for &colour in colours {
+        match style(colour) {
+            Style::Plain => writeln!(out, "  \"{}\";", colour).unwrap(),
+            s => writeln!(
+                out,
+                "  \"{}\" [style=filled, fillcolor=\"{}\"];",
+                colour,
+                s.fill()
+            )
+            .unwrap(),
+        }
+    }
+    for edge in edges {
+        writeln!(
+            out,
+            "  \"{}\" -> \"{}\" [label=\"{}\"];",
+            edge.from, edge.to, edge.quantity
+        )
+        .unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
+
+// Mermaid ids can't contain spaces, so nodes are numbered and labelled
+// with their colour.
+fn to_mermaid(colours: &[&str], edges: &[Edge], style: impl Fn(&str) -> Style) -> String {
+    let ids: HashMap<&str, usize> = colours.iter().enumerate().map(|(i, &c)| (c, i)).collect();
+    let mut out = String::from("graph TD\n");
+    for (idx, colour) in colours.iter().enumerate() {
+        writeln!(out, "  n{}[\"{}\"]", idx, colour).unwrap();
+    }
+    for edge in edges {
+        writeln!(
+            out,
+            "  n{} -->|{}| n{}",
+            ids[edge.from], edge.quantity, ids[edge.to]
+        )
+        .unwrap();
+    }
+    for s in [Style::Focus, Style::Ancestor, Style::Descendant].iter() {
+        let members: Vec<String> = colours
+            .iter()
+            .enumerate()
+            .filter(|(_, &c)| style(c) == *s)
+            .map(|(idx, _)| format!("n{}", idx))
+            .collect();
+        if !members.is_empty() {
+            writeln!(out, "  classDef {} fill:{}", s.class(), s.fill()).unwrap();
+            writeln!(out, "  class {} {}", members.join(","), s.class()).unwrap();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::{export, ExportOptions, Format};
+    use crate::graph::{BagGraph, GraphError};
+    use crate::Rule;
+
+    fn rules() -> Vec<Rule> {
+        [
+            "light red bags contain 1 bright white bag.",
+            "bright white bags contain 1 shiny gold bag.",
+            "shiny gold bags contain 2 dark olive bags.",
+            "dark olive bags contain 3 faded blue bags.",
+            "dotted black bags contain 4 faded blue bags.",
+        ]
+        .iter()
+        .map(|r| Rule::parse(r).unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn test_dot() {
+        let rules = rules();
+        let graph = BagGraph::new(&rules).unwrap();
+        let options = ExportOptions {
+            ancestors_of: Some("shiny gold".to_string()),
+            descendants_of: Some("shiny gold".to_string()),
+            around: Some("shiny gold".to_string()),
+        };
+        assert_eq!(
+            concat!(
+                "digraph bags {\n",
+                "  \"light red\" [style=filled, fillcolor=\"#add8e6\"];\n",
+                "  \"bright white\" [style=filled, fillcolor=\"#add8e6\"];\n",
+                "  \"shiny gold\" [style=filled, fillcolor=\"#ffd700\"];\n",
+                "  \"dark olive\" [style=filled, fillcolor=\"#90ee90\"];\n",
+                "  \"faded blue\" [style=filled, fillcolor=\"#90ee90\"];\n",
+                "  \"light red\" -> \"bright white\" [label=\"1\"];\n",
+                "  \"bright white\" -> \"shiny gold\" [label=\"1\"];\n",
+                "  \"shiny gold\" -> \"dark olive\" [label=\"2\"];\n",
+                "  \"dark olive\" -> \"faded blue\" [label=\"3\"];\n",
+                "}\n",
+            ),
+            export(&rules, &graph, Format::Dot, &options).unwrap()
+        );
+
+        let everything = export(&rules, &graph, Format::Dot, &ExportOptions::default()).unwrap();
+        assert!(everything.contains("  \"dotted black\";\n"));
+        assert!(everything.contains("  \"dotted black\" -> \"faded blue\" [label=\"4\"];\n"));
+    }
+
+    #[test]
+    fn test_mermaid() {
+        let rules = rules();
+        let graph = BagGraph::new(&rules).unwrap();
+        let options = ExportOptions {
+            descendants_of: Some("dark olive".to_string()),
+            ..ExportOptions::default()
+        };
+        assert_eq!(
+            concat!(
+                "graph TD\n",
+                "  n0[\"light red\"]\n",
+                "  n1[\"bright white\"]\n",
+                "  n2[\"shiny gold\"]\n",
+                "  n3[\"dark olive\"]\n",
+                "  n4[\"faded blue\"]\n",
+                "  n5[\"dotted black\"]\n",
+                "  n0 -->|1| n1\n",
+                "  n1 -->|1| n2\n",
+                "  n2 -->|2| n3\n",
+                "  n3 -->|3| n4\n",
+                "  n5 -->|4| n4\n",
+                "  classDef focus fill:#ffd700\n",
+                "  class n3 focus\n",
+                "  classDef descendant fill:#90ee90\n",
+                "  class n4 descendant\n",
+            ),
+            export(&rules, &graph, Format::Mermaid, &options).unwrap()
+        );
+
+        let unknown = ExportOptions {
+            around: Some("plaid".to_string()),
+            ..ExportOptions::default()
+        };
+        assert_eq!(
+            Err(GraphError::UnknownColour("plaid".to_string())),
+            export(&rules, &graph, Format::Mermaid, &unknown)
+        );
+    }
+}
@@ -0,0 +1,455 @@
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+
+use crate::Rule;
+
+#[derive(Debug, PartialEq)]
+pub enum GraphError {
+    DuplicateRule(String),
+    /// The colours around a cycle, starting and ending with the same one.
+    Cycle(Vec<String>),
+    UnknownColour(String),
+    Overflow(String),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GraphError::DuplicateRule(colour) => {
+                write!(f, "there is more than one rule for {} bags", colour)
+            }
+            GraphError::Cycle(colours) => {
+                write!(f, "bags contain themselves: {}", colours.join(" -> "))
+            }
+            GraphError::UnknownColour(colour) => write!(f, "no bags are {}", colour),
+            GraphError::Overflow(colour) => {
+                write!(f, "too many bags to count inside {}", colour)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mark {
+    Unvisited,
+    InProgress,
+    Done,
+}
+
+/// The containment rules as a graph. Colours that are only ever mentioned
+/// as contents are treated as empty bags. Building the graph fails if any
+/// bag could end up inside itself, so every walk over it terminates.
+pub struct BagGraph {
+    colours: Vec<String>,
+    index: HashMap<String, usize>,
+    contents: Vec<Vec<(usize, u32)>>,
+    containers: Vec<Vec<usize>>,
+}
+
+impl BagGraph {
+    pub fn new(rules: &[Rule]) -> Result<BagGraph, GraphError> {
+        let mut graph = BagGraph {
+            colours: Vec::new(),
+            index: HashMap::new(),
+            contents: Vec::new(),
+            containers: Vec::new(),
+        };
+        let mut has_rule = Vec::new();
+        for rule in rules {
+            let node = graph.node(&rule.colour);
+            has_rule.resize(graph.colours.len(), false);
+            if has_rule[node] {
+                return Err(GraphError::DuplicateRule(rule.colour.clone()));
+            }
+            has_rule[node] = true;
+            for constraint in rule.allowed_contents.iter() {
+                let child = graph.node(&constraint.colour);
+                graph.contents[node].push((child, constraint.quantity));
+                graph.containers[child].push(node);
+            }
+        }
+        if let Some(cycle) = graph.find_cycle() {
+            return Err(GraphError::Cycle(cycle));
+        }
+        Ok(graph)
+    }
+
+    fn node(&mut self, colour: &str) -> usize {
+        if let Some(&idx) = self.index.get(colour) {
+            return idx;
+        }
+        let idx = self.colours.len();
+        self.index.insert(colour.to_string(), idx);
+        self.colours.push(colour.to_string());
+        self.contents.push(Vec::new());
+        self.containers.push(Vec::new());
+        idx
+    }
+
+    pub fn lookup(&self, colour: &str) -> Result<usize, GraphError> {
+        self.index
+            .get(colour)
+            .copied()
+            .ok_or_else(|| GraphError::UnknownColour(colour.to_string()))
+    }
+
+    // An iterative depth-first search, so deep rule sets can't overflow the
+    // stack. The stack holds each node with the next child to look at.
+    fn find_cycle(&self) -> Option<Vec<String>> {
+        let mut marks = vec![Mark::Unvisited; self.colours.len()];
+        for start in 0..self.colours.len() {
+            if marks[start] != Mark::Unvisited {
+                continue;
+            }
+            let mut stack = vec![(start, 0)];
+            marks[start] = Mark::InProgress;
+            while let Some(top) = stack.last_mut() {
+                let (node, next) = *top;
+                top.1 += 1;
+                match self.contents[node].get(next) {
+                    Some(&(child, _)) => match marks[child] {
+                        Mark::Unvisited => {
+                            marks[child] = Mark::InProgress;
+                            stack.push((child, 0));
+                        }
+                        Mark::InProgress => {
+                            let from = stack.iter().position(|&(n, _)| n == child).unwrap();
+                            let mut cycle: Vec<String> = stack[from..]
+                                .iter()
+                                .map(|&(n, _)| self.colours[n].clone())
+                                .collect();
+                            cycle.push(self.colours[child].clone());
+                            return Some(cycle);
+                        }
+                        Mark::Done => {}
+                    },
+                    None => {
+                        marks[node] = Mark::Done;
+                        stack.pop();
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Every node reachable from `start` by following `edges`, not
+    /// including `start` itself unless it's reachable from itself.
+    fn reachable(&self, start: usize, edges: impl Fn(usize) -> Vec<usize>) -> Vec<usize> {
+        let mut seen = vec![false; self.colours.len()];
+        let mut found = Vec::new();
+        let mut stack = vec![start];
+        while let Some(node) = stack.pop() {
+            for next in edges(node) {
+                if !seen[next] {
+                    seen[next] = true;
+                    found.push(next);
+                    stack.push(next);
+                }
+            }
+        }
+        found
+    }
+
+    /// The colours of every bag that can eventually hold a `colour` bag.
+    pub fn ancestors(&self, colour: &str) -> Result<BTreeSet<&str>, GraphError> {
+        let start = self.lookup(colour)?;
+        Ok(self
+            .reachable(start, |n| self.containers[n].clone())
+            .into_iter()
+            .map(|n| self.colours[n].as_str())
+            .collect())
+    }
+
+    /// The colours of every bag that can end up inside a `colour` bag.
+    pub fn descendants(&self, colour: &str) -> Result<BTreeSet<&str>, GraphError> {
+        let start = self.lookup(colour)?;
+        Ok(self
+            .reachable(start, |n| {
+                self.contents[n].iter().map(|&(c, _)| c).collect()
+            })
+            .into_iter()
+            .map(|n| self.colours[n].as_str())
+            .collect())
+    }
+
+    /// Works out a value for `start` from the values of the bags it holds,
+    /// visiting children before their parents and each bag only once.
+    pub fn fold_contents<T: Clone>(
+        &self,
+        start: usize,
+        combine: impl Fn(usize, &[(T, u32)]) -> Option<T>,
+    ) -> Option<T> {
+        self.fold_all_contents(start, combine)
+            .map(|mut values| values.pop().unwrap().1)
+    }
+
+    /// Like `fold_contents`, but keeps the value of every bag `start`
+    /// holds, in the order they were worked out: no bag comes before one
+    /// it holds, and `start` is last.
+    pub fn fold_all_contents<T: Clone>(
+        &self,
+        start: usize,
+        combine: impl Fn(usize, &[(T, u32)]) -> Option<T>,
+    ) -> Option<Vec<(usize, T)>> {
+        let mut order = Vec::new();
+        let mut values: Vec<Option<T>> = vec![None; self.colours.len()];
+        let mut stack = vec![start];
+        while let Some(&node) = stack.last() {
+            let pending: Vec<usize> = self.contents[node]
+                .iter()
+                .map(|&(child, _)| child)
+                .filter(|&child| values[child].is_none())
+                .collect();
+            if !pending.is_empty() {
+                stack.extend(pending);
+                continue;
+            }
+            stack.pop();
+            if values[node].is_some() {
+                continue;
+            }
+            let children: Vec<(T, u32)> = self.contents[node]
+                .iter()
+                .map(|&(child, quantity)| (values[child].clone().unwrap(), quantity))
+                .collect();
+            values[node] = Some(combine(node, &children)?);
+            order.push(node);
+        }
+        Some(
+            order
+                .into_iter()
+                .map(|node| (node, values[node].take().unwrap()))
+                .collect(),
+        )
+    }
+
+    /// How many bags a single `colour` bag has to hold, not counting
+    /// itself.
+    pub fn count_contained(&self, colour: &str) -> Result<u128, GraphError> {
+        let start = self.lookup(colour)?;
+        let overflowed = Cell::new(start);
+        self.fold_contents(start, |node, children: &[(u128, u32)]| {
+            overflowed.set(node);
+            children.iter().try_fold(0u128, |sum, &(inside, quantity)| {
+                sum.checked_add(inside.checked_add(1)?.checked_mul(quantity as u128)?)
+            })
+        })
+        .ok_or_else(|| GraphError::Overflow(self.colours[overflowed.get()].clone()))
+    }
+
+    /// How many levels of bags are nested inside a `colour` bag.
+    pub fn depth(&self, colour: &str) -> Result<usize, GraphError> {
+        let start = self.lookup(colour)?;
+        Ok(self
+            .fold_contents(start, |_, children| {
+                Some(children.iter().map(|&(d, _)| d + 1).max().unwrap_or(0))
+            })
+            .unwrap())
+    }
+
+    /// The bags a `colour` bag directly holds, and how many of each.
+    pub fn contents(&self, colour: &str) -> Result<Vec<(&str, u32)>, GraphError> {
+        let node = self.lookup(colour)?;
+        Ok(self.contents[node]
+            .iter()
+            .map(|&(child, quantity)| (self.colours[child].as_str(), quantity))
+            .collect())
+    }
+
+    /// The bags that directly hold a `colour` bag.
+    pub fn containers(&self, colour: &str) -> Result<Vec<&str>, GraphError> {
+        let node = self.lookup(colour)?;
+        Ok(self.containers[node]
+            .iter()
+            .map(|&parent| self.colours[parent].as_str())
+            .collect())
+    }
+
+    /// The shortest chain of bags from `outer` down to `inner`, if an
+    /// `outer` bag can hold an `inner` one at all.
+    pub fn path(&self, outer: &str, inner: &str) -> Result<Option<Vec<&str>>, GraphError> {
+        let (start, end) = (self.lookup(outer)?, self.lookup(inner)?);
+        let mut previous: Vec<Option<usize>> = vec![None; self.colours.len()];
+        let mut queue = VecDeque::from(vec![start]);
+        while let Some(node) = queue.pop_front() {
+            if node == end {
+                let mut path = vec![self.colours[end].as_str()];
+                let mut at = end;
+                while let Some(p) = previous[at] {
+                    path.push(self.colours[p].as_str());
+                    at = p;
+                }
+                path.reverse();
+                return Ok(Some(path));
+            }
+            for &(child, _) in self.contents[node].iter() {
+                if previous[child].is_none() && child != start {
+                    previous[child] = Some(node);
+                    queue.push_back(child);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Bags that nothing else can hold.
+    pub fn roots(&self) -> Vec<&str> {
+        (0..self.colours.len())
+            .filter(|&n| self.containers[n].is_empty())
+            .map(|n| self.colours[n].as_str())
+            .collect()
+    }
+
+    /// Bags that can't hold anything.
+    pub fn leaves(&self) -> Vec<&str> {
+        (0..self.colours.len())
+            .filter(|&n| self.contents[n].is_empty())
+            .map(|n| self.colours[n].as_str())
+            .collect()
+    }
+
+    pub fn colours(&self) -> &[String] {
+        &self.colours
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::graph::{BagGraph, GraphError};
+    use crate::{Constraint, Rule};
+
+    fn graph(rules: &[&str]) -> Result<BagGraph, GraphError> {
+        let rules: Vec<Rule> = rules.iter().map(|r| Rule::parse(r).unwrap()).collect();
+        BagGraph::new(&rules)
+    }
+
+    fn sample() -> BagGraph {
+        graph(&[
+            "light red bags contain 1 bright white bag, 2 muted yellow bags.",
+            "dark orange bags contain 3 bright white bags, 4 muted yellow bags.",
+            "bright white bags contain 1 shiny gold bag.",
+            "muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.",
+            "shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.",
+            "dark olive bags contain 3 faded blue bags, 4 dotted black bags.",
+            "vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.",
+            "faded blue bags contain no other bags.",
+            "dotted black bags contain no other bags.",
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sample() {
+        let g = sample();
+        let ancestors: BTreeSet<&str> =
+            ["bright white", "muted yellow", "dark orange", "light red"]
+                .iter()
+                .copied()
+                .collect();
+        assert_eq!(ancestors, g.ancestors("shiny gold").unwrap());
+        assert_eq!(4, g.descendants("shiny gold").unwrap().len());
+        assert_eq!(32, g.count_contained("shiny gold").unwrap());
+        assert_eq!(0, g.count_contained("faded blue").unwrap());
+        assert_eq!(4, g.depth("light red").unwrap());
+        assert_eq!(0, g.depth("dotted black").unwrap());
+        assert_eq!(
+            Some(vec![
+                "dark orange",
+                "bright white",
+                "shiny gold",
+                "vibrant plum"
+            ]),
+            g.path("dark orange", "vibrant plum").unwrap()
+        );
+        assert_eq!(None, g.path("shiny gold", "light red").unwrap());
+        assert_eq!(vec!["light red", "dark orange"], g.roots());
+        assert_eq!(vec!["faded blue", "dotted black"], g.leaves());
+        assert_eq!(
+            vec!["bright white", "muted yellow"],
+            g.containers("shiny gold").unwrap()
+        );
+        assert_eq!(
+            vec![("faded blue", 3), ("dotted black", 4)],
+            g.contents("dark olive").unwrap()
+        );
+        assert_eq!(
+            Err(GraphError::UnknownColour("plaid".to_string())),
+            g.count_contained("plaid")
+        );
+    }
+
+    #[test]
+    fn test_cycles() {
+        assert_eq!(
+            Some(GraphError::Cycle(vec![
+                "dark red".to_string(),
+                "dark orange".to_string(),
+                "dark yellow".to_string(),
+                "dark red".to_string(),
+            ])),
+            graph(&[
+                "shiny gold bags contain 2 dark red bags.",
+                "dark red bags contain 2 dark orange bags.",
+                "dark orange bags contain 2 dark yellow bags.",
+                "dark yellow bags contain 1 dark red bag, 1 faded blue bag.",
+            ])
+            .err()
+        );
+        assert_eq!(
+            Some(GraphError::Cycle(vec![
+                "shiny gold".to_string(),
+                "shiny gold".to_string()
+            ])),
+            graph(&["shiny gold bags contain 1 shiny gold bag."]).err()
+        );
+        assert_eq!(
+            Some(GraphError::DuplicateRule("shiny gold".to_string())),
+            graph(&[
+                "shiny gold bags contain no other bags.",
+                "shiny gold bags contain 1 faded blue bag.",
+            ])
+            .err()
+        );
+    }
+
+    #[test]
+    fn test_deep_and_wide() {
+        // every layer holds two of each bag in the next, which is far too
+        // many paths to walk one at a time
+        let name = |layer: usize, side: &str| format!("layer{} {}", layer, side);
+        let rules: Vec<Rule> = (0..100)
+            .flat_map(|layer| {
+                ["a", "b"].iter().map(move |side| Rule {
+                    colour: name(layer, side),
+                    allowed_contents: vec![
+                        Constraint {
+                            quantity: 2,
+                            colour: name(layer + 1, "a"),
+                        },
+                        Constraint {
+                            quantity: 2,
+                            colour: name(layer + 1, "b"),
+                        },
+                    ],
+                })
+            })
+            .collect();
+        let g = BagGraph::new(&rules).unwrap();
+
+        // 4 + 4^2 + ... + 4^10
+        assert_eq!(1_398_100, g.count_contained("layer90 a").unwrap());
+        // 4 + ... + 4^63 fits in a u128, but one more layer doesn't
+        assert_eq!(
+            (u128::MAX / 3 - 1) & !3,
+            g.count_contained("layer37 b").unwrap()
+        );
+        match g.count_contained("layer0 a") {
+            Err(GraphError::Overflow(colour)) => assert!(colour.starts_with("layer36 ")),
+            other => panic!("expected an overflow, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use regex::Regex;
+
+use crate::graph::{BagGraph, GraphError};
+use crate::Rule;
+
+#[derive(Debug, PartialEq)]
+pub enum LintKind {
+    Unparseable,
+    Undefined(String),
+    Duplicate {
+        colour: String,
+        first_line: usize,
+    },
+    /// Defined, but neither holds nor is held by the target colour, even
+    /// indirectly.
+    Unreachable {
+        colour: String,
+        target: String,
+    },
+    /// The target colour isn't in any rule. Not tied to a line.
+    UnknownTarget(String),
+    SelfContainment(String),
+    Cycle(Vec<String>),
+    Pluralisation(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Lint {
+    pub line: usize,
+    pub kind: LintKind,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "line {}: ", self.line)?;
+        }
+        match &self.kind {
+            LintKind::Unparseable => write!(f, "not a bag rule"),
+            LintKind::Undefined(colour) => write!(f, "{} bags have no rule", colour),
+            LintKind::Duplicate { colour, first_line } => write!(
+                f,
+                "{} bags are already defined on line {}",
+                colour, first_line
+            ),
+            LintKind::Unreachable { colour, target } => {
+                write!(f, "{} bags have nothing to do with {} bags", colour, target)
+            }
+            LintKind::UnknownTarget(colour) => write!(f, "no rule mentions {} bags", colour),
+            LintKind::SelfContainment(colour) => write!(f, "{} bags contain themselves", colour),
+            LintKind::Cycle(colours) => {
+                write!(f, "bags contain themselves: {}", colours.join(" -> "))
+            }
+            LintKind::Pluralisation(text) => write!(f, "'{}' should agree in number", text),
+        }
+    }
+}
+
+// The rule parser accepts "bag" and "bags" after any quantity, so this
+// looks at the raw text.
+fn pluralisation(line: &str) -> Vec<String> {
+    lazy_static! {
+        static ref QUANTITY_RE: Regex = Regex::new("([0-9]+) ([a-z ]+?) (bags?)\\b").unwrap();
+    }
+    QUANTITY_RE
+        .captures_iter(line)
+        .filter(|x| {
+            let one = &x[1] == "1";
+            let plural = &x[3] == "bags";
+            one == plural
+        })
+        .map(|x| x[0].to_string())
+        .collect()
+}
+
+/// Every colour that can be reached from `start` by following `edges`,
+/// including `start` itself. Works whether or not there are cycles.
+fn reachable<'a>(edges: &HashMap<&'a str, Vec<&'a str>>, start: &'a str) -> BTreeSet<&'a str> {
+    let mut seen: BTreeSet<&str> = BTreeSet::new();
+    let mut queue = vec![start];
+    while let Some(colour) = queue.pop() {
+        if seen.insert(colour) {
+            queue.extend(edges.get(colour).into_iter().flatten());
+        }
+    }
+    seen
+}
+
+/// Checks a rule file, given as its lines, for mistakes the puzzle input
+/// never makes. Rules after the first for a colour are ignored for the
+/// checks that need the whole graph.
+pub fn lint(lines: &[String], target: &str) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    let mut rules: Vec<Rule> = Vec::new();
+    let mut defined: HashMap<String, usize> = HashMap::new();
+    let mut referenced: Vec<(usize, String)> = Vec::new();
+
+    for (idx, line) in lines.iter().enumerate() {
+        let line_number = idx + 1;
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        for text in pluralisation(line) {
+            lints.push(Lint {
+                line: line_number,
+                kind: LintKind::Pluralisation(text),
+            });
+        }
+        let mut rule = match Rule::parse(line) {
+            Some(rule) => rule,
+            None => {
+                lints.push(Lint {
+                    line: line_number,
+                    kind: LintKind::Unparseable,
+                });
+                continue;
+            }
+        };
+        if let Some(&first_line) = defined.get(&rule.colour) {
+            lints.push(Lint {
+                line: line_number,
+                kind: LintKind::Duplicate {
+                    colour: rule.colour.clone(),
+                    first_line,
+                },
+            });
+            continue;
+        }
+        for c in rule.allowed_contents.iter() {
+            if c.colour == rule.colour {
+                lints.push(Lint {
+                    line: line_number,
+                    kind: LintKind::SelfContainment(c.colour.clone()),
+                });
+            }
+            referenced.push((line_number, c.colour.clone()));
+        }
+        // already reported, and left out of the graph so they can't hide
+        // a longer cycle
+        let colour = rule.colour.clone();
+        rule.allowed_contents.retain(|c| c.colour != colour);
+        defined.insert(colour, line_number);
+        rules.push(rule);
+    }
+
+    let mut reported: BTreeSet<&str> = BTreeSet::new();
+    for (line, colour) in referenced.iter() {
+        if !defined.contains_key(colour) && reported.insert(colour) {
+            lints.push(Lint {
+                line: *line,
+                kind: LintKind::Undefined(colour.clone()),
+            });
+        }
+    }
+
+    let mut contents: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut containers: HashMap<&str, Vec<&str>> = HashMap::new();
+    for rule in rules.iter() {
+        for c in rule.allowed_contents.iter() {
+            contents.entry(&rule.colour).or_default().push(&c.colour);
+            containers.entry(&c.colour).or_default().push(&rule.colour);
+        }
+    }
+    let known = defined.contains_key(target) || referenced.iter().any(|(_, c)| c == target);
+    if known {
+        let ancestors = reachable(&containers, target);
+        let descendants = reachable(&contents, target);
+        for rule in rules.iter() {
+            let colour = rule.colour.as_str();
+            if !ancestors.contains(colour) && !descendants.contains(colour) {
+                lints.push(Lint {
+                    line: defined[colour],
+                    kind: LintKind::Unreachable {
+                        colour: colour.to_string(),
+                        target: target.to_string(),
+                    },
+                });
+            }
+        }
+    } else {
+        lints.push(Lint {
+            line: 0,
+            kind: LintKind::UnknownTarget(target.to_string()),
+        });
+    }
+
+    if let Err(GraphError::Cycle(colours)) = BagGraph::new(&rules) {
+        lints.push(Lint {
+            line: defined[&colours[0]],
+            kind: LintKind::Cycle(colours),
+        });
+    }
+
+    lints.sort_by_key(|l| l.line);
+    lints
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lint::{lint, Lint, LintKind};
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_clean() {
+        let rules = lines(concat!(
+            "light red bags contain 1 shiny gold bag.\n",
+            "shiny gold bags contain 2 faded blue bags.\n",
+            "faded blue bags contain no other bags.\n",
+        ));
+        assert_eq!(Vec::<Lint>::new(), lint(&rules, "shiny gold"));
+    }
+
+    #[test]
+    fn test_problems() {
+        let rules = lines(concat!(
+            "light red bags contain 1 shiny gold bags.\n",
+            "shiny gold bags contain 2 faded blue bag, 1 plaid bag.\n",
+            "faded blue bags contain no other bags.\n",
+            "\n",
+            "shiny gold bags contain no other bags.\n",
+            "dotted black bags contain 1 dotted black bag.\n",
+            "wavy teal bags contain no other bags.\n",
+            "this is not a rule\n",
+        ));
+        let found: Vec<String> = lint(&rules, "shiny gold")
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "line 1: '1 shiny gold bags' should agree in number",
+                "line 2: '2 faded blue bag' should agree in number",
+                "line 2: plaid bags have no rule",
+                "line 5: shiny gold bags are already defined on line 2",
+                "line 6: dotted black bags contain themselves",
+                "line 6: dotted black bags have nothing to do with shiny gold bags",
+                "line 7: wavy teal bags have nothing to do with shiny gold bags",
+                "line 8: not a bag rule",
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn test_graph_checks() {
+        let rules = lines(concat!(
+            "light red bags contain 1 shiny gold bag.\n",
+            "shiny gold bags contain no other bags.\n",
+            "wavy teal bags contain 2 dim grey bags.\n",
+            "dim grey bags contain no other bags.\n",
+        ));
+        let found = lint(&rules, "shiny gold");
+        assert_eq!(2, found.len());
+        assert_eq!(
+            Lint {
+                line: 3,
+                kind: LintKind::Unreachable {
+                    colour: "wavy teal".to_string(),
+                    target: "shiny gold".to_string()
+                }
+            },
+            found[0]
+        );
+
+        let cyclic = lines(concat!(
+            "light red bags contain 1 shiny gold bag.\n",
+            "shiny gold bags contain 1 light red bag.\n",
+        ));
+        assert_eq!(
+            vec![Lint {
+                line: 1,
+                kind: LintKind::Cycle(vec![
+                    "light red".to_string(),
+                    "shiny gold".to_string(),
+                    "light red".to_string()
+                ])
+            }],
+            lint(&cyclic, "shiny gold")
+        );
+
+        // a bag holding itself doesn't hide a longer cycle, and neither
+        // stops unrelated bags being reported
+        let tangled = lines(concat!(
+            "dim grey bags contain 1 dim grey bag.\n",
+            "light red bags contain 1 shiny gold bag.\n",
+            "shiny gold bags contain 1 light red bag.\n",
+            "wavy teal bags contain no other bags.\n",
+        ));
+        let found: Vec<String> = lint(&tangled, "shiny gold")
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "line 1: dim grey bags contain themselves",
+                "line 1: dim grey bags have nothing to do with shiny gold bags",
+                "line 2: bags contain themselves: light red -> shiny gold -> light red",
+                "line 4: wavy teal bags have nothing to do with shiny gold bags",
+            ],
+            found
+        );
+
+        assert_eq!(
+            vec![Lint {
+                line: 0,
+                kind: LintKind::UnknownTarget("muted lime".to_string())
+            }],
+            lint(&tangled[3..], "muted lime")
+        );
+        assert_eq!(
+            "no rule mentions muted lime bags",
+            lint(&tangled[3..], "muted lime")[0].to_string()
+        );
+    }
+}
@@ -2,10 +2,24 @@
 extern crate lazy_static;
 extern crate regex;
 use regex::Regex;
-use std::collections::HashMap;
 use std::env;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::process;
+
+mod export;
+mod graph;
+mod lint;
+mod query;
+mod weights;
+
+use export::{ExportOptions, Format};
+use graph::BagGraph;
+use query::{QueryHelper, Session};
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
+use weights::{AttributeTable, Limits};
 
 struct Rule {
     colour: String,
@@ -13,127 +27,246 @@ struct Rule {
 }
 
 struct Constraint {
-    quantity: u8,
+    quantity: u32,
     colour: String,
 }
 
 impl Rule {
-    fn parse(rule_str: &str) -> Rule {
+    fn parse(rule_str: &str) -> Option<Rule> {
         lazy_static! {
-            static ref RULE_RE: Regex = Regex::new("([a-z ]+) bags contain (.+)\\.").unwrap();
+            static ref RULE_RE: Regex = Regex::new("^([a-z ]+) bags contain (.+)\\.$").unwrap();
         }
 
-        match RULE_RE.captures(rule_str) {
-            Some(x) => {
-                let colour = x.get(1).unwrap().as_str().to_string();
-                let constraints_str = x.get(2).unwrap().as_str();
-                let allowed_contents: Vec<Constraint> = match constraints_str {
-                    "no other bags" => Vec::new(),
-                    _ => Rule::parse_constraints(constraints_str),
-                };
-
-                Rule {
-                    colour,
-                    allowed_contents,
-                }
-            }
-            None => unreachable!(),
-        }
+        let x = RULE_RE.captures(rule_str)?;
+        let colour = x.get(1).unwrap().as_str().to_string();
+        let constraints_str = x.get(2).unwrap().as_str();
+        let allowed_contents: Vec<Constraint> = match constraints_str {
+            "no other bags" => Vec::new(),
+            _ => Rule::parse_constraints(constraints_str)?,
+        };
+
+        Some(Rule {
+            colour,
+            allowed_contents,
+        })
     }
 
-    fn parse_constraints(constraints_str: &str) -> Vec<Constraint> {
+    fn parse_constraints(constraints_str: &str) -> Option<Vec<Constraint>> {
         lazy_static! {
-            static ref CONSTRAINT_RE: Regex = Regex::new("([1-9][0-9]*) ([a-z ]+) bags?").unwrap();
+            static ref CONSTRAINT_RE: Regex =
+                Regex::new("^([1-9][0-9]*) ([a-z ]+) bags?$").unwrap();
         }
 
         constraints_str
             .split(", ")
-            .map(|s| match CONSTRAINT_RE.captures(s) {
-                Some(x) => Constraint {
-                    quantity: x.get(1).unwrap().as_str().parse().unwrap(),
+            .map(|s| {
+                let x = CONSTRAINT_RE.captures(s)?;
+                Some(Constraint {
+                    quantity: x.get(1).unwrap().as_str().parse().ok()?,
                     colour: x.get(2).unwrap().as_str().to_string(),
-                },
-                None => unreachable!(),
+                })
             })
             .collect()
     }
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        panic!("Please specify rule file");
+fn take_option(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let idx = args.iter().position(|a| a == flag)?;
+    if idx + 1 >= args.len() {
+        panic!("{} needs a value", flag);
     }
-    let filename = args.get(1).unwrap();
+    args.remove(idx);
+    Some(args.remove(idx))
+}
+
+fn read_lines(filename: &str) -> Vec<String> {
     let file = File::open(filename).unwrap();
+    BufReader::new(file).lines().map_while(Result::ok).collect()
+}
 
-    let mut containers: HashMap<String, Vec<String>> = HashMap::new();
-    let mut all_rules: HashMap<String, Rule> = HashMap::new();
-    for r in BufReader::new(file).lines() {
-        if let Ok(line) = r {
-            let rule = Rule::parse(line.as_str());
+fn lint(mut args: Vec<String>) {
+    let target = take_option(&mut args, "--target").unwrap_or_else(|| "shiny gold".to_string());
+    let filename = args.get(2).expect("Please specify rule file");
+    let lints = lint::lint(&read_lines(filename), &target);
+    for l in lints.iter() {
+        println!("{}", l);
+    }
+    if !lints.is_empty() {
+        process::exit(1);
+    }
+}
 
-            for c in rule.allowed_contents.iter() {
-                if !containers.contains_key(&c.colour) {
-                    containers.insert(c.colour.clone(), Vec::new());
-                }
-                containers
-                    .get_mut(&c.colour)
-                    .unwrap()
-                    .push(rule.colour.clone());
-            }
+fn take_limit(args: &mut Vec<String>, flag: &str) -> Option<f64> {
+    take_option(args, flag).map(|v| v.parse().expect("limits must be numbers"))
+}
+
+/// Prints what a packed bag weighs and costs, and exits with an error if
+/// it breaks any of the limits given.
+fn weigh(mut args: Vec<String>) {
+    let limits = Limits {
+        weight: take_limit(&mut args, "--max-weight"),
+        cost: take_limit(&mut args, "--max-cost"),
+        volume: take_limit(&mut args, "--max-volume"),
+    };
+    if args.len() < 4 {
+        panic!("Please specify rule and attribute files");
+    }
+    let (_, graph) = load(&args[2]);
+    let table = match AttributeTable::load(&args[3]) {
+        Ok(table) => table,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let colour = if args.len() > 4 {
+        args[4..].join(" ")
+    } else {
+        "shiny gold".to_string()
+    };
 
-            all_rules.insert(rule.colour.clone(), rule);
+    let packed = match weights::pack(&graph, &table, &colour) {
+        Ok(packed) => packed,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
         }
+    };
+    println!("A packed {} bag: {}", packed.colour, packed.total);
+    if !packed.heaviest.is_empty() {
+        println!("Heaviest contents:");
+        for (c, quantity, total) in packed.heaviest.iter() {
+            println!("  {} {}: {}", quantity, c, total);
+        }
+    }
+    if !packed.missing.is_empty() {
+        let missing: Vec<&str> = packed.missing.iter().map(|c| c.as_str()).collect();
+        println!("No attributes for: {}", missing.join(", "));
     }
 
-    let initial = &"shiny gold".to_string();
-    let mut visited: HashMap<String, bool> = HashMap::new();
-    dfs(initial, &containers, &mut visited);
-    visited.remove(initial);
+    let checks = limits.check(&packed.total);
+    for check in checks.iter() {
+        println!("{}", check);
+    }
+    if checks.iter().any(|c| !c.passed()) {
+        process::exit(1);
+    }
+}
 
-    let count = visited.len();
-    println!(
-        "Part 1: {} kinds of bags can ultimately contain a {}:",
-        count, initial
-    );
+fn load(filename: &str) -> (Vec<Rule>, BagGraph) {
+    let mut rules = Vec::new();
+    for (idx, line) in read_lines(filename).iter().enumerate() {
+        match Rule::parse(line.trim_end()) {
+            Some(rule) => rules.push(rule),
+            None if line.trim().is_empty() => {}
+            None => {
+                eprintln!("line {}: not a bag rule, try lint", idx + 1);
+                process::exit(1);
+            }
+        }
+    }
+    match BagGraph::new(&rules) {
+        Ok(graph) => (rules, graph),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
 
-    let total_bags = count_bags(initial, &all_rules) - 1;
-    println!("Part 2: {} can be held by a {}", total_bags, initial);
+fn export(mut args: Vec<String>) {
+    let format = match take_option(&mut args, "--format") {
+        Some(f) => Format::parse(&f).expect("--format must be dot or mermaid"),
+        None => Format::Dot,
+    };
+    let options = ExportOptions {
+        ancestors_of: take_option(&mut args, "--ancestors"),
+        descendants_of: take_option(&mut args, "--descendants"),
+        around: take_option(&mut args, "--around"),
+    };
+    let filename = args.get(2).expect("Please specify rule file");
+    let (rules, graph) = load(filename);
+    match export::export(&rules, &graph, format, &options) {
+        Ok(out) => print!("{}", out),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
 }
 
-fn dfs(
-    search: &String,
-    containers: &HashMap<String, Vec<String>>,
-    visited: &mut HashMap<String, bool>,
-) {
-    visited.insert(search.clone(), true);
-    match containers.get(search) {
-        Some(neighbours) => {
-            for n in neighbours.iter() {
-                dfs(n, containers, visited)
+/// Runs the command given after the file name, or reads commands until
+/// end of input if there isn't one.
+fn query(args: Vec<String>) {
+    let filename = args.get(2).expect("Please specify rule file");
+    let (_, graph) = load(filename);
+    let session = Session::new(&graph);
+    if args.len() > 3 {
+        match session.run(&args[3..].join(" ")) {
+            Ok(out) => print!("{}", out),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let mut editor: Editor<QueryHelper, DefaultHistory> = Editor::new().unwrap();
+    editor.set_helper(Some(QueryHelper {
+        session: Session::new(&graph),
+    }));
+    loop {
+        match editor.readline("bags> ") {
+            Ok(line) => {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let _ = editor.add_history_entry(line.as_str());
+                match session.run(&line) {
+                    Ok(out) => print!("{}", out),
+                    Err(e) => println!("{}", e),
+                }
+            }
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
             }
         }
-        None => return,
     }
 }
 
-fn count_bags(colour: &String, rules: &HashMap<String, Rule>) -> i32 {
-    rules
-        .get(colour)
-        .and_then(|r| {
-            Some(if r.allowed_contents.len() == 0 {
-                1
-            } else {
-                let contents: i32 = r
-                    .allowed_contents
-                    .iter()
-                    .map(|c| c.quantity as i32 * count_bags(&c.colour, rules))
-                    .sum();
-                contents + 1
-            })
-        })
-        .unwrap_or(1)
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        panic!("Please specify rule file");
+    }
+    match args[1].as_str() {
+        "export" => return export(args),
+        "query" => return query(args),
+        "lint" => return lint(args),
+        "weigh" => return weigh(args),
+        _ => {}
+    }
+    let (_, graph) = load(args.get(1).unwrap());
+
+    let initial = "shiny gold";
+    let count = graph.ancestors(initial).map(|a| a.len());
+    let total_bags = graph.count_contained(initial);
+    match (count, total_bags) {
+        (Ok(count), Ok(total_bags)) => {
+            println!(
+                "Part 1: {} kinds of bags can ultimately contain a {}:",
+                count, initial
+            );
+            println!("Part 2: {} can be held by a {}", total_bags, initial);
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -142,7 +275,8 @@ mod tests {
 
     #[test]
     fn test_parse_rule() {
-        let r = Rule::parse("light red bags contain 1 bright white bag, 2 muted yellow bags.");
+        let r =
+            Rule::parse("light red bags contain 1 bright white bag, 2 muted yellow bags.").unwrap();
         assert_eq!(String::from("light red"), r.colour);
         assert_eq!(2, r.allowed_contents.len());
         let c0 = &r.allowed_contents[0];
@@ -156,7 +290,7 @@ mod tests {
 
     #[test]
     fn test_parse_empty_rule() {
-        let r = Rule::parse("dotted black bags contain no other bags.");
+        let r = Rule::parse("dotted black bags contain no other bags.").unwrap();
         assert_eq!(String::from("dotted black"), r.colour);
         assert_eq!(0, r.allowed_contents.len());
     }
@@ -0,0 +1,273 @@
+use std::fmt::Write;
+
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+use crate::graph::BagGraph;
+
+const COMMANDS: [&str; 8] = [
+    "containers",
+    "contents",
+    "total",
+    "path",
+    "depth",
+    "roots",
+    "leaves",
+    "help",
+];
+
+const HELP: &str = "\
+containers <colour>   bags that hold a <colour> bag
+contents <colour>     bags a <colour> bag holds
+total <colour>        how many bags are inside a <colour> bag
+path <outer> <inner>  the shortest chain from <outer> down to <inner>
+depth <colour>        how deeply bags are nested inside a <colour> bag
+roots                 bags nothing else holds
+leaves                bags that hold nothing
+";
+
+pub struct Session<'a> {
+    graph: &'a BagGraph,
+}
+
+impl<'a> Session<'a> {
+    pub fn new(graph: &'a BagGraph) -> Session<'a> {
+        Session { graph }
+    }
+
+    /// Matches `text` to a colour, allowing any prefix that only one
+    /// colour starts with.
+    fn colour(&self, text: &str) -> Result<&'a str, String> {
+        let text = text.trim();
+        let colours = self.graph.colours();
+        if let Some(exact) = colours.iter().find(|c| *c == text) {
+            return Ok(exact);
+        }
+        let matches: Vec<&'a str> = colours
+            .iter()
+            .filter(|c| c.starts_with(text))
+            .map(|c| c.as_str())
+            .collect();
+        match matches.len() {
+            0 => Err(format!("no bags are {}", text)),
+            1 => Ok(matches[0]),
+            _ => Err(format!("'{}' could be {}", text, matches.join(", "))),
+        }
+    }
+
+    // Colour names have spaces in them, so try every split of the words
+    // until both halves name a colour.
+    fn two_colours(&self, text: &str) -> Result<(&'a str, &'a str), String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        (1..words.len())
+            .find_map(|split| {
+                let outer = self.colour(&words[..split].join(" ")).ok()?;
+                let inner = self.colour(&words[split..].join(" ")).ok()?;
+                Some((outer, inner))
+            })
+            .ok_or_else(|| format!("expected two colours in '{}'", text))
+    }
+
+    pub fn run(&self, line: &str) -> Result<String, String> {
+        let line = line.trim();
+        let (command, rest) = match line.find(' ') {
+            Some(idx) => (&line[..idx], line[idx..].trim()),
+            None => (line, ""),
+        };
+        let graph = self.graph;
+        let mut out = String::new();
+        match command {
+            "containers" => {
+                let colour = self.colour(rest)?;
+                let direct = graph.containers(colour).map_err(|e| e.to_string())?;
+                let all = graph.ancestors(colour).map_err(|e| e.to_string())?;
+                for c in direct {
+                    writeln!(out, "{}", c).unwrap();
+                }
+                writeln!(out, "({} kinds of bag can hold it eventually)", all.len()).unwrap();
+            }
+            "contents" => {
+                let colour = self.colour(rest)?;
+                for (c, quantity) in graph.contents(colour).map_err(|e| e.to_string())? {
+                    writeln!(out, "{} {}", quantity, c).unwrap();
+                }
+            }
+            "total" => {
+                let colour = self.colour(rest)?;
+                let total = graph.count_contained(colour).map_err(|e| e.to_string())?;
+                writeln!(out, "{}", total).unwrap();
+            }
+            "path" => {
+                let (outer, inner) = self.two_colours(rest)?;
+                match graph.path(outer, inner).map_err(|e| e.to_string())? {
+                    Some(path) => writeln!(out, "{}", path.join(" -> ")).unwrap(),
+                    None => writeln!(out, "{} bags can't hold {} bags", outer, inner).unwrap(),
+                }
+            }
+            "depth" => {
+                let colour = self.colour(rest)?;
+                let depth = graph.depth(colour).map_err(|e| e.to_string())?;
+                writeln!(out, "{}", depth).unwrap();
+            }
+            "roots" => graph
+                .roots()
+                .iter()
+                .for_each(|c| writeln!(out, "{}", c).unwrap()),
+            "leaves" => graph
+                .leaves()
+                .iter()
+                .for_each(|c| writeln!(out, "{}", c).unwrap()),
+            "help" => out.push_str(HELP),
+            _ => return Err(format!("unknown command '{}', try help", command)),
+        }
+        Ok(out)
+    }
+
+    /// Candidates for the word being typed at the end of `line`, and where
+    /// in the line they start. Completes command names first, then colours.
+    pub fn complete(&self, line: &str) -> (usize, Vec<String>) {
+        let command_end = match line.find(' ') {
+            Some(idx) => idx,
+            None => {
+                let commands = COMMANDS.iter().filter(|c| c.starts_with(line));
+                return (0, commands.map(|c| c.to_string()).collect());
+            }
+        };
+
+        // For path, the second colour starts after the first complete one.
+        let mut start = command_end + 1 + line[command_end + 1..].len()
+            - line[command_end + 1..].trim_start().len();
+        if &line[..command_end] == "path" {
+            for colour in self.graph.colours() {
+                let after = &line[start..];
+                if after.starts_with(colour.as_str()) && after[colour.len()..].starts_with(' ') {
+                    start += colour.len() + 1;
+                    break;
+                }
+            }
+        }
+        let prefix = &line[start..];
+        let mut candidates: Vec<String> = self
+            .graph
+            .colours()
+            .iter()
+            .filter(|c| c.starts_with(prefix))
+            .cloned()
+            .collect();
+        candidates.sort();
+        (start, candidates)
+    }
+}
+
+/// Hooks `Session::complete` into the line editor.
+pub struct QueryHelper<'a> {
+    pub session: Session<'a>,
+}
+
+impl Completer for QueryHelper<'_> {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.session.complete(&line[..pos]))
+    }
+}
+
+impl Hinter for QueryHelper<'_> {
+    type Hint = String;
+}
+
+impl Highlighter for QueryHelper<'_> {}
+
+impl Validator for QueryHelper<'_> {}
+
+impl Helper for QueryHelper<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::BagGraph;
+    use crate::query::Session;
+    use crate::Rule;
+
+    fn graph() -> BagGraph {
+        let rules: Vec<Rule> = [
+            "light red bags contain 1 bright white bag, 2 muted yellow bags.",
+            "bright white bags contain 1 shiny gold bag.",
+            "muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.",
+            "shiny gold bags contain 1 dark olive bag, 2 shiny purple bags.",
+            "dark olive bags contain 3 faded blue bags.",
+        ]
+        .iter()
+        .map(|r| Rule::parse(r).unwrap())
+        .collect();
+        BagGraph::new(&rules).unwrap()
+    }
+
+    #[test]
+    fn test_commands() {
+        let graph = graph();
+        let session = Session::new(&graph);
+        assert_eq!(
+            Ok("bright white\nmuted yellow\n(3 kinds of bag can hold it eventually)\n".to_string()),
+            session.run("containers shiny gold")
+        );
+        assert_eq!(
+            Ok("1 dark olive\n2 shiny purple\n".to_string()),
+            session.run("contents shiny g")
+        );
+        assert_eq!(Ok("6\n".to_string()), session.run("total shiny gold"));
+        assert_eq!(Ok("4\n".to_string()), session.run("depth light red"));
+        assert_eq!(
+            Ok("light red -> bright white -> shiny gold -> dark olive\n".to_string()),
+            session.run("path light red dark olive")
+        );
+        assert_eq!(
+            Ok("dark olive bags can't hold light red bags\n".to_string()),
+            session.run("path dark olive light red")
+        );
+        assert_eq!(Ok("light red\n".to_string()), session.run("roots"));
+        assert_eq!(
+            Ok("faded blue\nshiny purple\n".to_string()),
+            session.run("leaves")
+        );
+
+        assert_eq!(
+            Err("'shiny' could be shiny gold, shiny purple".to_string()),
+            session.run("total shiny")
+        );
+        assert_eq!(
+            Err("no bags are plaid".to_string()),
+            session.run("depth plaid")
+        );
+        assert!(session.run("launch").is_err());
+    }
+
+    #[test]
+    fn test_completion() {
+        let graph = graph();
+        let session = Session::new(&graph);
+        assert_eq!(
+            (0, vec!["containers".to_string(), "contents".to_string()]),
+            session.complete("con")
+        );
+        assert_eq!(
+            (
+                6,
+                vec!["shiny gold".to_string(), "shiny purple".to_string()]
+            ),
+            session.complete("total shi")
+        );
+        assert_eq!(
+            (15, vec!["dark olive".to_string()]),
+            session.complete("path light red d")
+        );
+        assert_eq!((5, Vec::<String>::new()), session.complete("path plaid"));
+    }
+}
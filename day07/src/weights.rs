@@ -0,0 +1,329 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+
+use serde::Deserialize;
+
+use crate::graph::{BagGraph, GraphError};
+
+/// What a single empty bag of one colour weighs, costs and takes up.
+/// Anything left out of the attribute file is zero.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Attributes {
+    pub weight: f64,
+    pub cost: f64,
+    pub volume: f64,
+}
+
+impl Attributes {
+    fn add(&self, other: &Attributes) -> Attributes {
+        Attributes {
+            weight: self.weight + other.weight,
+            cost: self.cost + other.cost,
+            volume: self.volume + other.volume,
+        }
+    }
+
+    fn times(&self, quantity: u128) -> Attributes {
+        let q = quantity as f64;
+        Attributes {
+            weight: self.weight * q,
+            cost: self.cost * q,
+            volume: self.volume * q,
+        }
+    }
+}
+
+impl fmt::Display for Attributes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "weight {}, cost {}, volume {}",
+            self.weight, self.cost, self.volume
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum AttributeError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    NotFinite {
+        colour: String,
+        attribute: &'static str,
+        value: f64,
+    },
+    Negative {
+        colour: String,
+        attribute: &'static str,
+        value: f64,
+    },
+}
+
+impl fmt::Display for AttributeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AttributeError::Io(e) => write!(f, "could not read attributes: {}", e),
+            AttributeError::Parse(e) => write!(f, "invalid attributes: {}", e),
+            AttributeError::NotFinite {
+                colour,
+                attribute,
+                value,
+            } => write!(f, "{} {} of {} isn't a number", colour, attribute, value),
+            AttributeError::Negative {
+                colour,
+                attribute,
+                value,
+            } => write!(f, "{} {} of {} is negative", colour, attribute, value),
+        }
+    }
+}
+
+/// Attributes by colour, read from a TOML table per colour:
+///
+/// ```toml
+/// ["shiny gold"]
+/// weight = 0.4
+/// cost = 12
+/// ```
+pub struct AttributeTable {
+    colours: BTreeMap<String, Attributes>,
+}
+
+impl AttributeTable {
+    pub fn parse(text: &str) -> Result<AttributeTable, AttributeError> {
+        let colours: BTreeMap<String, Attributes> =
+            toml::from_str(text).map_err(AttributeError::Parse)?;
+        for (colour, a) in colours.iter() {
+            let values = [("weight", a.weight), ("cost", a.cost), ("volume", a.volume)];
+            for &(attribute, value) in values.iter() {
+                // TOML allows nan and inf, which can't be added up or sorted
+                if !value.is_finite() {
+                    return Err(AttributeError::NotFinite {
+                        colour: colour.clone(),
+                        attribute,
+                        value,
+                    });
+                }
+                // and a negative amount would let a bag get under a limit
+                // by holding more
+                if value < 0.0 {
+                    return Err(AttributeError::Negative {
+                        colour: colour.clone(),
+                        attribute,
+                        value,
+                    });
+                }
+            }
+        }
+        Ok(AttributeTable { colours })
+    }
+
+    pub fn load(path: &str) -> Result<AttributeTable, AttributeError> {
+        AttributeTable::parse(&fs::read_to_string(path).map_err(AttributeError::Io)?)
+    }
+}
+
+pub struct Packed {
+    pub colour: String,
+    /// The bag itself plus everything it has to hold.
+    pub total: Attributes,
+    /// Every bag inside, however deep, with how many there are altogether
+    /// and what they add up to with their own contents, heaviest first.
+    pub heaviest: Vec<(String, u128, Attributes)>,
+    /// Colours inside the bag with no attributes, counted as zero.
+    pub missing: BTreeSet<String>,
+}
+
+/// Works out the attributes of a fully packed `colour` bag.
+pub fn pack(graph: &BagGraph, table: &AttributeTable, colour: &str) -> Result<Packed, GraphError> {
+    let colours = graph.colours();
+    let start = graph.lookup(colour)?;
+    // what one bag of each colour comes to, innermost first
+    let totals = graph
+        .fold_all_contents(start, |node, children: &[(Attributes, u32)]| {
+            let own = table
+                .colours
+                .get(&colours[node])
+                .copied()
+                .unwrap_or_default();
+            Some(children.iter().fold(own, |sum, (inside, quantity)| {
+                sum.add(&inside.times(*quantity as u128))
+            }))
+        })
+        .unwrap();
+
+    // how many of each colour one `colour` bag holds, worked out from the
+    // outside in so every container is counted before what it holds
+    let mut counts: HashMap<&str, u128> = HashMap::new();
+    counts.insert(colour, 1);
+    for &(node, _) in totals.iter().rev() {
+        let count = counts[colours[node].as_str()];
+        for (inside, quantity) in graph.contents(&colours[node])? {
+            let held = count.saturating_mul(quantity as u128);
+            let entry = counts.entry(inside).or_insert(0);
+            *entry = entry.saturating_add(held);
+        }
+    }
+
+    let mut heaviest: Vec<(String, u128, Attributes)> = totals
+        .iter()
+        .filter(|&&(node, _)| node != start)
+        .map(|(node, each)| {
+            let count = counts[colours[*node].as_str()];
+            (colours[*node].clone(), count, each.times(count))
+        })
+        .collect();
+    heaviest.sort_by(|a, b| b.2.weight.total_cmp(&a.2.weight).then(a.0.cmp(&b.0)));
+
+    let mut missing: BTreeSet<String> = graph
+        .descendants(colour)?
+        .into_iter()
+        .filter(|c| !table.colours.contains_key(*c))
+        .map(|c| c.to_string())
+        .collect();
+    if !table.colours.contains_key(colour) {
+        missing.insert(colour.to_string());
+    }
+
+    Ok(Packed {
+        colour: colour.to_string(),
+        total: totals.last().unwrap().1,
+        heaviest,
+        missing,
+    })
+}
+
+#[derive(Default)]
+pub struct Limits {
+    pub weight: Option<f64>,
+    pub cost: Option<f64>,
+    pub volume: Option<f64>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LimitCheck {
+    pub attribute: &'static str,
+    pub limit: f64,
+    pub actual: f64,
+}
+
+impl LimitCheck {
+    pub fn passed(&self) -> bool {
+        self.actual <= self.limit
+    }
+}
+
+impl fmt::Display for LimitCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let verdict = if self.passed() { "within" } else { "over" };
+        write!(
+            f,
+            "{} {} is {} the limit of {}",
+            self.attribute, self.actual, verdict, self.limit
+        )
+    }
+}
+
+impl Limits {
+    pub fn check(&self, total: &Attributes) -> Vec<LimitCheck> {
+        [
+            ("weight", self.weight, total.weight),
+            ("cost", self.cost, total.cost),
+            ("volume", self.volume, total.volume),
+        ]
+        .iter()
+        .filter_map(|&(attribute, limit, actual)| {
+            Some(LimitCheck {
+                attribute,
+                limit: limit?,
+                actual,
+            })
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::BagGraph;
+    use crate::weights::{pack, AttributeTable, Attributes, Limits};
+    use crate::Rule;
+
+    #[test]
+    fn test_pack() {
+        let rules: Vec<Rule> = [
+            "shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.",
+            "dark olive bags contain 3 faded blue bags, 4 dotted black bags.",
+            "vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.",
+            "faded blue bags contain no other bags.",
+            "dotted black bags contain no other bags.",
+        ]
+        .iter()
+        .map(|r| Rule::parse(r).unwrap())
+        .collect();
+        let graph = BagGraph::new(&rules).unwrap();
+        let table = AttributeTable::parse(concat!(
+            "[\"shiny gold\"]\nweight = 1.0\ncost = 10.0\n",
+            "[\"dark olive\"]\nweight = 0.5\n",
+            "[\"vibrant plum\"]\nweight = 0.5\nvolume = 2.0\n",
+            "[\"faded blue\"]\nweight = 0.25\ncost = 1.0\n",
+        ))
+        .unwrap();
+
+        let packed = pack(&graph, &table, "shiny gold").unwrap();
+        // 1 + (0.5 + 3 * 0.25) + 2 * (0.5 + 5 * 0.25)
+        assert_eq!(
+            Attributes {
+                weight: 5.75,
+                cost: 23.0,
+                volume: 4.0,
+            },
+            packed.total
+        );
+        // faded blue is never held directly, but 3 + 2 * 5 of them weigh
+        // more than the dark olive bag holding some
+        let heaviest: Vec<(&str, u128, f64)> = packed
+            .heaviest
+            .iter()
+            .map(|(c, quantity, total)| (c.as_str(), *quantity, total.weight))
+            .collect();
+        assert_eq!(
+            vec![
+                ("vibrant plum", 2, 3.5),
+                ("faded blue", 13, 3.25),
+                ("dark olive", 1, 1.25),
+                ("dotted black", 16, 0.0),
+            ],
+            heaviest
+        );
+        assert_eq!(
+            vec!["dotted black"],
+            packed.missing.iter().collect::<Vec<_>>()
+        );
+
+        let limits = Limits {
+            weight: Some(5.0),
+            cost: Some(50.0),
+            volume: None,
+        };
+        let checks = limits.check(&packed.total);
+        assert_eq!(2, checks.len());
+        assert!(!checks[0].passed());
+        assert_eq!("weight 5.75 is over the limit of 5", checks[0].to_string());
+        assert!(checks[1].passed());
+
+        assert!(AttributeTable::parse("[\"shiny gold\"]\nmass = 1.0\n").is_err());
+        match AttributeTable::parse("[\"shiny gold\"]\ncost = 1.0\nweight = nan\n") {
+            Err(e) => assert_eq!("shiny gold weight of NaN isn't a number", e.to_string()),
+            Ok(_) => panic!("nan should be rejected"),
+        }
+        assert!(AttributeTable::parse("[\"dark olive\"]\nvolume = -inf\n").is_err());
+        match AttributeTable::parse("[\"dark olive\"]\ncost = -2.5\n") {
+            Err(e) => assert_eq!("dark olive cost of -2.5 is negative", e.to_string()),
+            Ok(_) => panic!("negative costs should be rejected"),
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fmt::Write;
+
+use crate::cpu::{Isa, Op, Operand};
+
+#[derive(Debug, PartialEq)]
+pub enum AsmErrorKind {
+    UnknownOpcode(String),
+    MissingOperand,
+    TrailingInput(String),
+    InvalidName(String),
+    InvalidNumber(String),
+    InvalidRegister(String),
+    Redefined(String),
+    Undefined(String),
+    OutOfRange(i64),
+}
+
+/// Something wrong on a (1-based) line of assembly source.
+#[derive(Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AsmErrorKind::UnknownOpcode(op) => write!(f, "unsupported opcode '{}'", op),
+            AsmErrorKind::MissingOperand => write!(f, "missing argument"),
+            AsmErrorKind::TrailingInput(rest) => write!(f, "unexpected '{}'", rest),
+            AsmErrorKind::InvalidName(name) => write!(f, "'{}' is not a valid name", name),
+            AsmErrorKind::InvalidNumber(n) => write!(f, "invalid number '{}'", n),
+            AsmErrorKind::InvalidRegister(r) => write!(f, "invalid register '{}'", r),
+            AsmErrorKind::Redefined(name) => write!(f, "'{}' is already defined", name),
+            AsmErrorKind::Undefined(name) => write!(f, "'{}' is not defined", name),
+            AsmErrorKind::OutOfRange(value) => write!(f, "{} does not fit in an argument", value),
+        }
+    }
+}
+
+fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+enum Symbol {
+    Label(usize),
+    Constant(i64),
+}
+
+struct Instruction<'a> {
+    line: usize,
+    opcode: &'a str,
+    operand: Option<&'a str>,
+}
+
+/// Assembles source where each line is empty, a comment, a constant
+/// definition or an instruction, optionally preceded by labels:
+///
+/// ```text
+/// # comments start with '#'
+/// STEP = +2
+/// loop: acc STEP
+///       jmp loop
+/// ```
+///
+/// An argument is a number, a constant or a label, or a register for the
+/// instructions that take one. Labels turn into the offset from the
+/// instruction using them to the one they mark, so `jmp loop` above is
+/// `jmp -1`. A label after the last instruction marks the end of the
+/// program. Instructions from any version of the ISA are accepted.
+pub fn assemble(source: &str) -> Result<Vec<Op>, AsmError> {
+    let mut symbols: HashMap<&str, Symbol> = HashMap::new();
+    let mut instructions: Vec<Instruction> = Vec::new();
+
+    for (idx, line) in source.lines().enumerate() {
+        let line_no = idx + 1;
+        let error = |kind| AsmError {
+            line: line_no,
+            kind,
+        };
+        let mut rest = line.split('#').next().unwrap().trim();
+        if let Some((name, value)) = rest.split_once('=') {
+            let value = match resolve_constant(value.trim(), &symbols) {
+                Ok(value) => value,
+                Err(kind) => return Err(error(kind)),
+            };
+            define(&mut symbols, name.trim(), Symbol::Constant(value)).map_err(error)?;
+            continue;
+        }
+        while let Some((label, after)) = rest.split_once(':') {
+            define(
+                &mut symbols,
+                label.trim(),
+                Symbol::Label(instructions.len()),
+            )
+            .map_err(error)?;
+            rest = after.trim();
+        }
+        if rest.is_empty() {
+            continue;
+        }
+
+        let mut parts = rest.split_whitespace();
+        let opcode = parts.next().unwrap();
+        let operand = parts.next();
+        if let Some(extra) = parts.next() {
+            return Err(error(AsmErrorKind::TrailingInput(extra.to_string())));
+        }
+        instructions.push(Instruction {
+            line: line_no,
+            opcode,
+            operand,
+        });
+    }
+
+    instructions
+        .iter()
+        .enumerate()
+        .map(|(pos, instruction)| {
+            let error = |kind| AsmError {
+                line: instruction.line,
+                kind,
+            };
+            let opcode = instruction.opcode;
+            let text = match (Op::operand(opcode), instruction.operand) {
+                (None, _) => return Err(error(AsmErrorKind::UnknownOpcode(opcode.to_string()))),
+                (Some(Operand::Nothing), None) => opcode.to_string(),
+                (Some(Operand::Nothing), Some(extra)) => {
+                    return Err(error(AsmErrorKind::TrailingInput(extra.to_string())))
+                }
+                (Some(_), None) => return Err(error(AsmErrorKind::MissingOperand)),
+                (Some(Operand::Register), Some(register)) => format!("{} {}", opcode, register),
+                (Some(Operand::Number), Some(operand)) => {
+                    let value = match symbols.get(operand) {
+                        Some(Symbol::Label(target)) => *target as i64 - pos as i64,
+                        Some(Symbol::Constant(value)) => *value,
+                        None => resolve_constant(operand, &symbols).map_err(error)?,
+                    };
+                    if value < i32::MIN as i64 || value > i32::MAX as i64 {
+                        return Err(error(AsmErrorKind::OutOfRange(value)));
+                    }
+                    format!("{} {}", opcode, value)
+                }
+            };
+            // only a register can still be wrong
+            Op::parse_with(&text, Isa::LATEST).map_err(|_| {
+                error(AsmErrorKind::InvalidRegister(
+                    instruction.operand.unwrap_or_default().to_string(),
+                ))
+            })
+        })
+        .collect()
+}
+
+fn define<'a>(
+    symbols: &mut HashMap<&'a str, Symbol>,
+    name: &'a str,
+    symbol: Symbol,
+) -> Result<(), AsmErrorKind> {
+    if !is_name(name) {
+        return Err(AsmErrorKind::InvalidName(name.to_string()));
+    }
+    if symbols.insert(name, symbol).is_some() {
+        return Err(AsmErrorKind::Redefined(name.to_string()));
+    }
+    Ok(())
+}
+
+// Numbers and constants only: labels can't be resolved until every
+// instruction has been seen.
+fn resolve_constant(s: &str, symbols: &HashMap<&str, Symbol>) -> Result<i64, AsmErrorKind> {
+    if s.is_empty() {
+        return Err(AsmErrorKind::MissingOperand);
+    }
+    if is_name(s) {
+        return match symbols.get(s) {
+            Some(Symbol::Constant(value)) => Ok(*value),
+            _ => Err(AsmErrorKind::Undefined(s.to_string())),
+        };
+    }
+    s.parse()
+        .map_err(|_| AsmErrorKind::InvalidNumber(s.to_string()))
+}
+
+/// Turns a program back into source `assemble` accepts, with a label on
+/// every instruction a jump or `call` lands on and each instruction's address in a
+/// comment. Jumps that leave the program keep their numeric offset.
+pub fn disassemble(program: &[Op]) -> String {
+    let mut labels: BTreeMap<usize, String> = BTreeMap::new();
+    for (pos, op) in program.iter().enumerate() {
+        if let Some(offset) = op.jump_offset() {
+            let target = pos as i64 + offset as i64;
+            if target >= 0 && target <= program.len() as i64 {
+                labels.insert(target as usize, String::new());
+            }
+        }
+    }
+    for (n, (&target, name)) in labels.iter_mut().enumerate() {
+        *name = if target == program.len() {
+            "end".to_string()
+        } else {
+            format!("L{}", n)
+        };
+    }
+
+    let mut out = String::new();
+    for (pos, op) in program.iter().enumerate() {
+        if let Some(name) = labels.get(&pos) {
+            writeln!(out, "{}:", name).unwrap();
+        }
+        let label = op
+            .jump_offset()
+            .map(|offset| pos as i64 + offset as i64)
+            .filter(|&target| target >= 0)
+            .and_then(|target| labels.get(&(target as usize)));
+        let text = match label {
+            Some(name) => format!("{} {}", op.name(), name),
+            None => op.to_string(),
+        };
+        writeln!(out, "    {:<16}# {}", text, pos).unwrap();
+    }
+    if let Some(name) = labels.get(&program.len()) {
+        writeln!(out, "{}:", name).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::{assemble, disassemble, AsmError, AsmErrorKind};
+    use crate::cpu::{sample_program, Isa, Op};
+
+    #[test]
+    fn test_assemble() {
+        let source = "\
+# count to three, then leave
+STEP = +1
+TIMES = 3
+start: acc STEP     # 0
+       nop TIMES
+loop:
+       jmp done
+       jmp loop
+done: end: nop -2
+";
+        assert_eq!(
+            Ok(vec![
+                Op::Acc(1),
+                Op::Nop(3),
+                Op::Jmp(2),
+                Op::Jmp(-1),
+                Op::Nop(-2)
+            ]),
+            assemble(source)
+        );
+        assert_eq!(Ok(vec![Op::Jmp(1)]), assemble("jmp out\nout:"));
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let error = |line, kind| Err(AsmError { line, kind });
+        assert_eq!(
+            error(2, AsmErrorKind::Undefined("nowhere".to_string())),
+            assemble("nop +0\njmp nowhere")
+        );
+        assert_eq!(
+            error(2, AsmErrorKind::Redefined("a".to_string())),
+            assemble("a: nop +0\na: nop +0")
+        );
+        assert_eq!(
+            error(1, AsmErrorKind::UnknownOpcode("hcf".to_string())),
+            assemble("hcf +0")
+        );
+        assert_eq!(error(1, AsmErrorKind::MissingOperand), assemble("jmp"));
+        assert_eq!(
+            error(1, AsmErrorKind::TrailingInput("+1".to_string())),
+            assemble("ret +1")
+        );
+        assert_eq!(
+            error(1, AsmErrorKind::InvalidRegister("r9".to_string())),
+            assemble("sto r9")
+        );
+        assert_eq!(
+            Ok(vec![Op::Call(2), Op::Ret, Op::Out]),
+            assemble("call sub\nret\nsub: out")
+        );
+        assert_eq!(
+            error(1, AsmErrorKind::TrailingInput("+2".to_string())),
+            assemble("acc +1 +2")
+        );
+        assert_eq!(
+            error(1, AsmErrorKind::InvalidName("2x".to_string())),
+            assemble("2x = 1")
+        );
+        assert_eq!(
+            error(1, AsmErrorKind::InvalidNumber("+x".to_string())),
+            assemble("acc +x")
+        );
+        assert_eq!(
+            error(1, AsmErrorKind::OutOfRange(1 << 40)),
+            assemble("acc 1099511627776")
+        );
+        assert_eq!(
+            "line 3: 'x' is not defined",
+            assemble("nop +0\n\nacc x").unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_disassemble() {
+        assert_eq!(
+            concat!(
+                "    nop +0          # 0\n",
+                "L0:\n",
+                "    acc +1          # 1\n",
+                "    jmp L2          # 2\n",
+                "L1:\n",
+                "    acc +3          # 3\n",
+                "    jmp L0          # 4\n",
+                "    acc -99         # 5\n",
+                "L2:\n",
+                "    acc +1          # 6\n",
+                "    jmp L1          # 7\n",
+                "    acc +6          # 8\n",
+            ),
+            disassemble(&sample_program())
+        );
+        assert_eq!(
+            "    jmp end         # 0\n    jmp -5          # 1\nend:\n",
+            disassemble(&[Op::Jmp(2), Op::Jmp(-5)])
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(
+            Ok(sample_program()),
+            assemble(&disassemble(&sample_program()))
+        );
+        let edges = vec![
+            Op::Jmp(0),
+            Op::Jmp(3),
+            Op::Jmp(-2),
+            Op::Jmp(i32::MIN),
+            Op::Nop(i32::MAX),
+            Op::Acc(-7),
+        ];
+        assert_eq!(Ok(edges.clone()), assemble(&disassemble(&edges)));
+        assert_eq!(Ok(Vec::new()), assemble(&disassemble(&[])));
+
+        let v2: Vec<Op> = [
+            "acc +3", "sto r2", "call +3", "jnz -1", "jz +4", "add r2", "out", "ret",
+        ]
+        .iter()
+        .map(|l| Op::parse_with(l, Isa::V2).unwrap())
+        .collect();
+        let source = disassemble(&v2);
+        assert!(source.contains("    call L1         # 2\n"));
+        assert!(source.contains("    jz end          # 4\n"));
+        assert_eq!(Ok(v2), assemble(&source));
+    }
+}
@@ -0,0 +1,878 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+use serde::{Deserialize, Serialize};
+
+use crate::trace::TraceEntry;
+
+/// Versions of the instruction set. Programs written for an older
+/// version run unchanged on a newer one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Isa {
+    /// `nop`, `acc` and `jmp`.
+    V1,
+    /// Adds conditional jumps, registers, a call stack and `out`.
+    V2,
+    /// Adds `snd` and `rcv` for talking to other CPUs in a network.
+    V3,
+}
+
+impl Isa {
+    pub const LATEST: Isa = Isa::V3;
+
+    pub fn parse(s: &str) -> Option<Isa> {
+        match s {
+            "1" | "v1" => Some(Isa::V1),
+            "2" | "v2" => Some(Isa::V2),
+            "3" | "v3" => Some(Isa::V3),
+            _ => None,
+        }
+    }
+
+    /// The oldest version that can run every instruction in `program`.
+    pub fn required(program: &[Op]) -> Isa {
+        program.iter().map(Op::isa).max().unwrap_or(Isa::V1)
+    }
+}
+
+impl fmt::Display for Isa {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Isa::V1 => write!(f, "v1"),
+            Isa::V2 => write!(f, "v2"),
+            Isa::V3 => write!(f, "v3"),
+        }
+    }
+}
+
+/// How many registers there are besides acc, named `r0` upwards.
+pub const REGISTERS: usize = 4;
+
+/// How deep `call` can nest before the CPU gives up.
+pub const MAX_STACK: usize = 1 << 16;
+
+/// How many distinct states loop detection remembers before giving up.
+pub const MAX_STATES: usize = 1 << 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Register(u8);
+
+impl Register {
+    fn parse(s: &str) -> Result<Register, String> {
+        s.strip_prefix('r')
+            .and_then(|n| n.parse::<u8>().ok())
+            .filter(|&n| (n as usize) < REGISTERS)
+            .map(Register)
+            .ok_or_else(|| format!("invalid register '{}'", s))
+    }
+
+    fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "r{}", self.0)
+    }
+}
+
+/// What an opcode takes as its argument.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    Number,
+    Register,
+    Nothing,
+}
+
+/// Serialised in the same text form the program is written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Op {
+    Nop(i32),
+    Acc(i32),
+    Jmp(i32),
+    /// Jump if acc is zero.
+    Jz(i32),
+    /// Jump if acc isn't zero.
+    Jnz(i32),
+    /// Jump if acc is greater than zero.
+    Jgz(i32),
+    /// Copy acc into a register.
+    Sto(Register),
+    /// Copy a register into acc.
+    Lod(Register),
+    /// Add a register to acc.
+    Add(Register),
+    /// Push the address of the next instruction and jump.
+    Call(i32),
+    /// Pop an address pushed by `call` and jump to it.
+    Ret,
+    /// Emit acc.
+    Out,
+    /// Send acc to the CPU with this id.
+    Snd(i32),
+    /// Take the oldest value sent to this CPU into acc, waiting for one if
+    /// there isn't one yet.
+    Rcv,
+}
+
+impl Op {
+    pub const OPCODES: [&'static str; 14] = [
+        "nop", "acc", "jmp", "jz", "jnz", "jgz", "sto", "lod", "add", "call", "ret", "out", "snd",
+        "rcv",
+    ];
+
+    /// Parses an instruction from the original instruction set.
+    pub fn parse(s: &str) -> Result<Op, String> {
+        Op::parse_with(s, Isa::V1)
+    }
+
+    pub fn parse_with(s: &str, isa: Isa) -> Result<Op, String> {
+        let mut parts = s.split_whitespace();
+        let opcode = parts.next().ok_or("missing opcode")?;
+        let argument = parts.next();
+        if parts.next().is_some() {
+            return Err(format!("too many arguments in '{}'", s));
+        }
+        let number = || -> Result<i32, String> {
+            let param = argument.ok_or("missing argument")?;
+            param
+                .parse()
+                .map_err(|_| format!("invalid argument '{}'", param))
+        };
+        let register = || Register::parse(argument.ok_or("missing argument")?);
+        let op = match opcode {
+            "nop" => Op::Nop(number()?),
+            "acc" => Op::Acc(number()?),
+            "jmp" => Op::Jmp(number()?),
+            "jz" => Op::Jz(number()?),
+            "jnz" => Op::Jnz(number()?),
+            "jgz" => Op::Jgz(number()?),
+            "sto" => Op::Sto(register()?),
+            "lod" => Op::Lod(register()?),
+            "add" => Op::Add(register()?),
+            "call" => Op::Call(number()?),
+            "ret" => Op::Ret,
+            "out" => Op::Out,
+            "snd" => Op::Snd(number()?),
+            "rcv" => Op::Rcv,
+            _ => return Err(format!("unsupported opcode '{}'", opcode)),
+        };
+        if argument.is_some() && Op::operand(opcode) == Some(Operand::Nothing) {
+            return Err(format!("{} takes no argument", opcode));
+        }
+        if op.isa() > isa {
+            return Err(format!("'{}' needs ISA {}", opcode, op.isa()));
+        }
+        Ok(op)
+    }
+
+    /// What `opcode` takes, or `None` if there's no such opcode.
+    pub fn operand(opcode: &str) -> Option<Operand> {
+        match opcode {
+            "sto" | "lod" | "add" => Some(Operand::Register),
+            "ret" | "out" | "rcv" => Some(Operand::Nothing),
+            _ if Op::OPCODES.contains(&opcode) => Some(Operand::Number),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Op::Nop(_) => "nop",
+            Op::Acc(_) => "acc",
+            Op::Jmp(_) => "jmp",
+            Op::Jz(_) => "jz",
+            Op::Jnz(_) => "jnz",
+            Op::Jgz(_) => "jgz",
+            Op::Sto(_) => "sto",
+            Op::Lod(_) => "lod",
+            Op::Add(_) => "add",
+            Op::Call(_) => "call",
+            Op::Ret => "ret",
+            Op::Out => "out",
+            Op::Snd(_) => "snd",
+            Op::Rcv => "rcv",
+        }
+    }
+
+    pub fn isa(&self) -> Isa {
+        match self {
+            Op::Nop(_) | Op::Acc(_) | Op::Jmp(_) => Isa::V1,
+            Op::Snd(_) | Op::Rcv => Isa::V3,
+            _ => Isa::V2,
+        }
+    }
+
+    /// The relative offset of any instruction that can jump.
+    pub fn jump_offset(&self) -> Option<i32> {
+        match self {
+            Op::Jmp(offset)
+            | Op::Jz(offset)
+            | Op::Jnz(offset)
+            | Op::Jgz(offset)
+            | Op::Call(offset) => Some(*offset),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::Nop(value)
+            | Op::Acc(value)
+            | Op::Jmp(value)
+            | Op::Jz(value)
+            | Op::Jnz(value)
+            | Op::Jgz(value)
+            | Op::Call(value)
+            | Op::Snd(value) => write!(f, "{} {:+}", self.name(), value),
+            Op::Sto(register) | Op::Lod(register) | Op::Add(register) => {
+                write!(f, "{} {}", self.name(), register)
+            }
+            Op::Ret | Op::Out | Op::Rcv => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+impl From<Op> for String {
+    fn from(op: Op) -> String {
+        op.to_string()
+    }
+}
+
+impl TryFrom<String> for Op {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Op, String> {
+        Op::parse_with(&s, Isa::LATEST)
+    }
+}
+
+/// Why the CPU stopped running.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExitReason {
+    /// Execution reached the instruction just past the end of the program.
+    Terminated,
+    /// The instruction at `at` was about to run a second time.
+    InfiniteLoop {
+        at: usize,
+        acc: i64,
+    },
+    JumpOutOfBounds {
+        from: usize,
+        target: i64,
+    },
+    StepLimit,
+    /// `call` at `at` would nest deeper than `MAX_STACK`.
+    StackOverflow {
+        at: usize,
+    },
+    /// `ret` at `at` with nothing to return to.
+    EmptyStack {
+        at: usize,
+    },
+    /// `rcv` at `at` with nothing to receive.
+    Blocked {
+        at: usize,
+    },
+}
+
+impl fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExitReason::Terminated => write!(f, "terminated"),
+            ExitReason::InfiniteLoop { at, acc } => {
+                write!(f, "infinite loop at {} with acc {}", at, acc)
+            }
+            ExitReason::JumpOutOfBounds { from, target } => {
+                write!(f, "jump from {} to {} is outside the program", from, target)
+            }
+            ExitReason::StepLimit => write!(f, "step limit reached"),
+            ExitReason::StackOverflow { at } => write!(f, "call stack overflow at {}", at),
+            ExitReason::EmptyStack { at } => write!(f, "ret with an empty call stack at {}", at),
+            ExitReason::Blocked { at } => write!(f, "blocked on rcv at {}", at),
+        }
+    }
+}
+
+/// Everything needed to pick a run back up where it left off. The fields
+/// added with ISA v2 are optional so older snapshots still load.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub acc: i64,
+    pub pos: usize,
+    pub steps: u64,
+    pub program: Vec<Op>,
+    #[serde(default)]
+    pub registers: [i64; REGISTERS],
+    #[serde(default)]
+    pub stack: Vec<usize>,
+    #[serde(default)]
+    pub output: Vec<i64>,
+    #[serde(default)]
+    pub inbox: VecDeque<i64>,
+    #[serde(default)]
+    pub outbox: Vec<(i32, i64)>,
+}
+
+/// Spots a CPU about to repeat itself. In v1 nothing but pos decides
+/// where execution goes, so running an instruction twice means going round
+/// forever. Past that, the whole state has to come round again, with the
+/// call stack compared by a fingerprint kept up to date as it changes.
+/// A program that never comes round again, say one counting up forever,
+/// would fill memory with states, so past `MAX_STATES` of them it's
+/// treated as having used up its steps.
+pub struct LoopDetector {
+    executed: Option<Vec<bool>>,
+    seen: HashSet<(usize, i64, [i64; REGISTERS], usize, u64, usize)>,
+    stack_hashes: Vec<u64>,
+}
+
+impl LoopDetector {
+    pub fn new(program: &[Op]) -> LoopDetector {
+        LoopDetector {
+            executed: match Isa::required(program) {
+                Isa::V1 => Some(vec![false; program.len()]),
+                _ => None,
+            },
+            seen: HashSet::new(),
+            stack_hashes: Vec::new(),
+        }
+    }
+
+    /// Records the state `cpu` is in before running the instruction at
+    /// `pos`, returning why it should stop if it has been there before or
+    /// there's no room left to remember it.
+    pub fn check(&mut self, cpu: &Cpu) -> Option<ExitReason> {
+        let repeated = match &mut self.executed {
+            Some(executed) => mem::replace(&mut executed[cpu.pos], true),
+            None if self.seen.len() >= MAX_STATES => return Some(ExitReason::StepLimit),
+            None => self.revisiting(cpu),
+        };
+        if repeated {
+            Some(ExitReason::InfiniteLoop {
+                at: cpu.pos,
+                acc: cpu.acc,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn revisiting(&mut self, cpu: &Cpu) -> bool {
+        // calls and returns only touch the top of the stack
+        self.stack_hashes.truncate(cpu.stack.len());
+        while self.stack_hashes.len() < cpu.stack.len() {
+            let mut hasher = DefaultHasher::new();
+            self.stack_hashes.last().hash(&mut hasher);
+            cpu.stack[self.stack_hashes.len()].hash(&mut hasher);
+            self.stack_hashes.push(hasher.finish());
+        }
+        !self.seen.insert((
+            cpu.pos,
+            cpu.acc,
+            cpu.registers,
+            cpu.stack.len(),
+            self.stack_hashes.last().copied().unwrap_or(0),
+            // nothing can add to the inbox of a CPU running on its own
+            cpu.inbox.len(),
+        ))
+    }
+}
+
+/// What it takes to undo one instruction.
+#[derive(Clone, Copy, Debug)]
+pub struct Undo {
+    pos: usize,
+    acc: i64,
+    /// What `sto` overwrote.
+    register: i64,
+}
+
+pub struct Cpu {
+    pub acc: i64,
+    pub pos: usize,
+    pub program: Vec<Op>,
+    pub steps: u64,
+    pub registers: [i64; REGISTERS],
+    pub stack: Vec<usize>,
+    /// Everything `out` has emitted, oldest first.
+    pub output: Vec<i64>,
+    /// Values sent to this CPU that `rcv` hasn't taken yet.
+    pub inbox: VecDeque<i64>,
+    /// Values `snd` has sent and the id of the CPU each is for, waiting to
+    /// be delivered.
+    pub outbox: Vec<(i32, i64)>,
+    step_limit: Option<u64>,
+}
+
+impl Cpu {
+    pub fn new(program: Vec<Op>) -> Cpu {
+        Cpu {
+            acc: 0,
+            pos: 0,
+            program,
+            steps: 0,
+            registers: [0; REGISTERS],
+            stack: Vec::new(),
+            output: Vec::new(),
+            inbox: VecDeque::new(),
+            outbox: Vec::new(),
+            step_limit: None,
+        }
+    }
+
+    pub fn with_step_limit(program: Vec<Op>, limit: u64) -> Cpu {
+        let mut cpu = Cpu::new(program);
+        cpu.step_limit = Some(limit);
+        cpu
+    }
+
+    /// Starts from a snapshot. Loop detection only knows about
+    /// instructions run since.
+    pub fn from_snapshot(snapshot: Snapshot) -> Cpu {
+        let mut cpu = Cpu::new(snapshot.program);
+        cpu.acc = snapshot.acc;
+        cpu.pos = snapshot.pos;
+        cpu.steps = snapshot.steps;
+        cpu.registers = snapshot.registers;
+        cpu.stack = snapshot.stack;
+        cpu.output = snapshot.output;
+        cpu.inbox = snapshot.inbox;
+        cpu.outbox = snapshot.outbox;
+        cpu
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            acc: self.acc,
+            pos: self.pos,
+            steps: self.steps,
+            program: self.program.clone(),
+            registers: self.registers,
+            stack: self.stack.clone(),
+            output: self.output.clone(),
+            inbox: self.inbox.clone(),
+            outbox: self.outbox.clone(),
+        }
+    }
+
+    /// Records what running the instruction at `pos` will change, to hand
+    /// to `undo` once it has run.
+    pub fn undo_point(&self) -> Undo {
+        Undo {
+            pos: self.pos,
+            acc: self.acc,
+            register: match self.program.get(self.pos) {
+                Some(Op::Sto(register)) => self.registers[register.index()],
+                _ => 0,
+            },
+        }
+    }
+
+    /// Puts back the last instruction `step` ran successfully.
+    pub fn undo(&mut self, undo: Undo) {
+        match self.program[undo.pos] {
+            Op::Sto(register) => self.registers[register.index()] = undo.register,
+            Op::Call(_) => {
+                self.stack.pop();
+            }
+            // ret jumped to the address it popped
+            Op::Ret => self.stack.push(self.pos),
+            Op::Out => {
+                self.output.pop();
+            }
+            Op::Snd(_) => {
+                self.outbox.pop();
+            }
+            // acc still holds what rcv took
+            Op::Rcv => self.inbox.push_front(self.acc),
+            _ => {}
+        }
+        self.pos = undo.pos;
+        self.acc = undo.acc;
+        self.steps -= 1;
+    }
+
+    /// Runs one instruction. Returns why the CPU can't go on, if it can't,
+    /// leaving the CPU as it was before the instruction that faulted.
+    pub fn step(&mut self) -> Option<ExitReason> {
+        let op = match self.program.get(self.pos) {
+            Some(&op) => op,
+            None => return Some(ExitReason::Terminated),
+        };
+        let here = self.pos as i64;
+        let branch = |offset: i32, taken: bool| {
+            if taken {
+                here + offset as i64
+            } else {
+                here + 1
+            }
+        };
+        let next = match op {
+            Op::Acc(value) => {
+                // wrapping rather than panicking; it would take billions of
+                // steps to get there
+                self.acc = self.acc.wrapping_add(value as i64);
+                here + 1
+            }
+            Op::Jmp(value) => here + value as i64,
+            Op::Nop(_) => here + 1,
+            Op::Jz(value) => branch(value, self.acc == 0),
+            Op::Jnz(value) => branch(value, self.acc != 0),
+            Op::Jgz(value) => branch(value, self.acc > 0),
+            Op::Sto(register) => {
+                self.registers[register.index()] = self.acc;
+                here + 1
+            }
+            Op::Lod(register) => {
+                self.acc = self.registers[register.index()];
+                here + 1
+            }
+            Op::Add(register) => {
+                self.acc = self.acc.wrapping_add(self.registers[register.index()]);
+                here + 1
+            }
+            Op::Call(value) => {
+                if self.stack.len() >= MAX_STACK {
+                    return Some(ExitReason::StackOverflow { at: self.pos });
+                }
+                here + value as i64
+            }
+            Op::Ret => match self.stack.last() {
+                Some(&address) => address as i64,
+                None => return Some(ExitReason::EmptyStack { at: self.pos }),
+            },
+            Op::Out => {
+                self.output.push(self.acc);
+                here + 1
+            }
+            Op::Snd(target) => {
+                self.outbox.push((target, self.acc));
+                here + 1
+            }
+            Op::Rcv => match self.inbox.pop_front() {
+                Some(value) => {
+                    self.acc = value;
+                    here + 1
+                }
+                None => return Some(ExitReason::Blocked { at: self.pos }),
+            },
+        };
+        if next < 0 || next > self.program.len() as i64 {
+            return Some(ExitReason::JumpOutOfBounds {
+                from: self.pos,
+                target: next,
+            });
+        }
+        match op {
+            Op::Call(_) => self.stack.push(self.pos + 1),
+            Op::Ret => {
+                self.stack.pop();
+            }
+            _ => {}
+        }
+        self.pos = next as usize;
+        self.steps += 1;
+        None
+    }
+
+    /// Runs until the program ends, faults, is about to repeat itself or
+    /// uses up its step limit.
+    pub fn run(&mut self) -> ExitReason {
+        self.run_traced(|_| {})
+    }
+
+    /// Like `run`, passing every instruction that completes to `observe`.
+    pub fn run_traced(&mut self, mut observe: impl FnMut(&TraceEntry)) -> ExitReason {
+        let mut detector = LoopDetector::new(&self.program);
+        loop {
+            if self.pos == self.program.len() {
+                return ExitReason::Terminated;
+            }
+            if let Some(reason) = detector.check(self) {
+                return reason;
+            }
+            if self.step_limit.is_some_and(|limit| self.steps >= limit) {
+                return ExitReason::StepLimit;
+            }
+            let (step, pos, acc_before) = (self.steps, self.pos, self.acc);
+            if let Some(reason) = self.step() {
+                return reason;
+            }
+            observe(&TraceEntry {
+                step,
+                pos,
+                op: self.program[pos],
+                acc_before,
+                acc_after: self.acc,
+                next: self.pos,
+            });
+        }
+    }
+}
+
+/// The example boot code from the puzzle, which most tests start from.
+#[cfg(test)]
+pub fn sample_program() -> Vec<Op> {
+    [
+        "nop +0", "acc +1", "jmp +4", "acc +3", "jmp -3", "acc -99", "acc +1", "jmp -4", "acc +6",
+    ]
+    .iter()
+    .map(|l| Op::parse(l).unwrap())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use crate::cpu::{sample_program, Cpu, ExitReason, Isa, Op, Snapshot, MAX_STACK, MAX_STATES};
+
+    fn program(source: &str) -> Vec<Op> {
+        source.lines().map(|l| Op::parse(l).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Ok(Op::Jmp(-4)), Op::parse("jmp -4"));
+        assert_eq!(Ok(Op::Acc(3)), Op::parse("acc +3"));
+        assert!(Op::parse("hcf +0").is_err());
+        assert!(Op::parse("nop").is_err());
+        assert!(Op::parse("nop +x").is_err());
+        assert!(Op::parse("").is_err());
+        assert_eq!("jmp -4", Op::Jmp(-4).to_string());
+        assert_eq!("nop +0", Op::Nop(0).to_string());
+    }
+
+    #[test]
+    fn test_exit_reasons() {
+        let sample = sample_program();
+        let mut cpu = Cpu::new(sample.clone());
+        assert_eq!(ExitReason::InfiniteLoop { at: 1, acc: 5 }, cpu.run());
+
+        let mut fixed = sample;
+        fixed[7] = Op::Nop(-4);
+        let mut cpu = Cpu::new(fixed);
+        assert_eq!(ExitReason::Terminated, cpu.run());
+        assert_eq!(8, cpu.acc);
+
+        // jumping to just past the end is a normal exit
+        let mut cpu = Cpu::new(program("jmp +2\nacc +1"));
+        assert_eq!(ExitReason::Terminated, cpu.run());
+
+        let mut cpu = Cpu::new(program("acc +1\njmp -2"));
+        assert_eq!(
+            ExitReason::JumpOutOfBounds {
+                from: 1,
+                target: -1
+            },
+            cpu.run()
+        );
+        assert_eq!(1, cpu.pos);
+        assert_eq!(1, cpu.acc);
+
+        let mut cpu = Cpu::new(program("jmp +3\nnop +0"));
+        assert_eq!(
+            ExitReason::JumpOutOfBounds { from: 0, target: 3 },
+            cpu.run()
+        );
+
+        let mut cpu = Cpu::with_step_limit(program("nop +0\nacc +1\nacc +1"), 2);
+        assert_eq!(ExitReason::StepLimit, cpu.run());
+        assert_eq!(1, cpu.acc);
+
+        let mut cpu = Cpu::new(Vec::new());
+        assert_eq!(ExitReason::Terminated, cpu.run());
+
+        let mut cpu = Cpu::new(program("jmp -2147483648"));
+        assert_eq!(
+            ExitReason::JumpOutOfBounds {
+                from: 0,
+                target: -2147483648
+            },
+            cpu.run()
+        );
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let sample = sample_program();
+        let mut cpu = Cpu::with_step_limit(sample.clone(), 4);
+        assert_eq!(ExitReason::StepLimit, cpu.run());
+
+        let json = serde_json::to_string(&cpu.snapshot()).unwrap();
+        assert!(json.contains("\"program\":[\"nop +0\",\"acc +1\",\"jmp +4\""));
+        let snapshot: Snapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            Snapshot {
+                acc: 2,
+                pos: 7,
+                steps: 4,
+                program: sample,
+                registers: [0; 4],
+                stack: Vec::new(),
+                output: Vec::new(),
+                inbox: VecDeque::new(),
+                outbox: Vec::new(),
+            },
+            snapshot
+        );
+
+        let mut resumed = Cpu::from_snapshot(snapshot);
+        assert_eq!(ExitReason::InfiniteLoop { at: 7, acc: 7 }, resumed.run());
+        assert_eq!(10, resumed.steps);
+
+        assert!(serde_json::from_str::<Op>("\"hcf +1\"").is_err());
+
+        // snapshots from before ISA v2 have no registers, stack or output
+        let old = r#"{"acc":3,"pos":1,"steps":2,"program":["nop +0","acc +3"]}"#;
+        let snapshot: Snapshot = serde_json::from_str(old).unwrap();
+        assert_eq!(Vec::<usize>::new(), snapshot.stack);
+
+        let mut cpu = Cpu::new(v2("call +2\njmp +3\nout\nret"));
+        cpu.step();
+        cpu.step();
+        let mut resumed = Cpu::from_snapshot(
+            serde_json::from_str(&serde_json::to_string(&cpu.snapshot()).unwrap()).unwrap(),
+        );
+        assert_eq!(vec![1], resumed.stack);
+        assert_eq!(vec![0], resumed.output);
+        assert_eq!(ExitReason::Terminated, resumed.run());
+        assert_eq!(vec![0], resumed.output);
+    }
+
+    fn v2(source: &str) -> Vec<Op> {
+        source
+            .lines()
+            .map(|l| Op::parse_with(l, Isa::V2).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_v2() {
+        assert_eq!("'jz' needs ISA v2", Op::parse("jz +2").unwrap_err());
+        for line in [
+            "jz +2", "jnz -1", "jgz +0", "sto r0", "lod r3", "add r1", "call +4", "ret", "out",
+        ]
+        .iter()
+        {
+            let op = Op::parse_with(line, Isa::V2).unwrap();
+            assert_eq!(*line, op.to_string());
+            assert_eq!(Isa::V2, op.isa());
+        }
+        assert_eq!(Ok(Op::Nop(1)), Op::parse_with("nop +1", Isa::V2));
+        assert!(Op::parse_with("sto r4", Isa::V2).is_err());
+        assert!(Op::parse_with("sto acc", Isa::V2).is_err());
+        assert!(Op::parse_with("lod", Isa::V2).is_err());
+        assert!(Op::parse_with("ret +1", Isa::V2).is_err());
+        assert!(Op::parse_with("out r0", Isa::V2).is_err());
+        assert_eq!(Isa::V1, Isa::required(&program("nop +0\njmp -1")));
+        assert_eq!(Isa::V2, Isa::required(&v2("nop +0\nout")));
+    }
+
+    #[test]
+    fn test_step_v2() {
+        // count r0 down from 3, emitting each value, via a subroutine
+        let mut cpu = Cpu::new(v2(concat!(
+            "acc +3\n",
+            "sto r0\n",
+            "call +4\n",
+            "lod r0\n",
+            "jgz -2\n",
+            "jmp +6\n",
+            "lod r0\n",
+            "out\n",
+            "acc -1\n",
+            "sto r0\n",
+            "ret\n",
+        )));
+        assert_eq!(ExitReason::Terminated, cpu.run());
+        assert_eq!(vec![3, 2, 1], cpu.output);
+        assert_eq!(0, cpu.acc);
+        assert!(cpu.stack.is_empty());
+
+        // undoing every step gets back to the start
+        let start = Cpu::new(cpu.program.clone()).snapshot();
+        let mut cpu = Cpu::from_snapshot(start.clone());
+        let mut undos = Vec::new();
+        while cpu.pos < cpu.program.len() {
+            undos.push(cpu.undo_point());
+            assert_eq!(None, cpu.step());
+        }
+        while let Some(undo) = undos.pop() {
+            cpu.undo(undo);
+        }
+        assert_eq!(start, cpu.snapshot());
+
+        let mut cpu = Cpu::new(v2("acc +1\nsto r1\nadd r1\nadd r1\njz +2\njnz +1\nnop +0"));
+        assert_eq!(ExitReason::Terminated, cpu.run());
+        assert_eq!(3, cpu.acc);
+        assert_eq!([0, 1, 0, 0], cpu.registers);
+
+        let mut cpu = Cpu::new(v2("ret"));
+        assert_eq!(ExitReason::EmptyStack { at: 0 }, cpu.run());
+
+        let mut cpu = Cpu::new(v2("call +0"));
+        assert_eq!(ExitReason::StackOverflow { at: 0 }, cpu.run());
+        assert_eq!(MAX_STACK, cpu.stack.len());
+
+        // a failed call leaves the stack alone
+        let mut cpu = Cpu::new(v2("call +5"));
+        assert_eq!(
+            ExitReason::JumpOutOfBounds { from: 0, target: 5 },
+            cpu.run()
+        );
+        assert!(cpu.stack.is_empty());
+
+        // revisiting an instruction is only a loop once the whole state
+        // comes round again
+        let mut cpu = Cpu::new(v2("acc +3\nacc -1\njz +2\njmp -2"));
+        assert_eq!(ExitReason::Terminated, cpu.run());
+        assert_eq!(0, cpu.acc);
+        let mut cpu = Cpu::new(v2("nop +0\nout\njmp -2"));
+        assert_eq!(ExitReason::InfiniteLoop { at: 0, acc: 0 }, cpu.run());
+        assert_eq!(vec![0], cpu.output);
+
+        // a state that never comes round again stops at the cap instead of
+        // remembering states forever
+        let mut cpu = Cpu::new(v2("acc +1\njmp -1\nout"));
+        assert_eq!(ExitReason::StepLimit, cpu.run());
+        assert_eq!(MAX_STATES as u64, cpu.steps);
+    }
+
+    #[test]
+    fn test_snd_rcv() {
+        assert_eq!(
+            "'rcv' needs ISA v3",
+            Op::parse_with("rcv", Isa::V2).unwrap_err()
+        );
+        let program: Vec<Op> = ["rcv", "acc +1", "snd +2", "rcv"]
+            .iter()
+            .map(|l| Op::parse_with(l, Isa::V3).unwrap())
+            .collect();
+        let mut cpu = Cpu::new(program);
+        cpu.inbox.push_back(41);
+        let start = cpu.snapshot();
+        let undo = cpu.undo_point();
+        assert_eq!(None, cpu.step());
+        assert_eq!(41, cpu.acc);
+        cpu.undo(undo);
+        assert_eq!(start, cpu.snapshot());
+
+        // blocking leaves everything as it was, ready to try again
+        assert_eq!(ExitReason::Blocked { at: 3 }, cpu.run());
+        assert_eq!(vec![(2, 42)], cpu.outbox);
+        assert_eq!(3, cpu.pos);
+        cpu.inbox.push_back(7);
+        assert_eq!(ExitReason::Terminated, cpu.run());
+        assert_eq!(7, cpu.acc);
+    }
+}
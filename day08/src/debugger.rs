@@ -0,0 +1,666 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::fs;
+
+use crate::cpu::{Cpu, ExitReason, Isa, LoopDetector, Op, Snapshot, Undo, MAX_STATES};
+
+const HELP: &str = "\
+break <addr>|<opcode>   stop before running an address or any instruction of a kind
+delete <n>              remove breakpoint n
+watch acc <op> <value>  stop when acc starts to satisfy <op> (== != < <= > >=)
+watch acc changes       stop whenever acc changes
+unwatch <n>             remove watch n
+step [n]                run one or n instructions
+continue                run until a breakpoint, watch, loop or exit
+back [n]                undo one or n instructions
+rcontinue               undo instructions until a breakpoint or the start
+list [radius]           disassemble around pos
+counts                  how many times each instruction has run
+info                    show pos, acc, registers, the call stack and breakpoints
+reset                   start the program again, keeping breakpoints
+save <file>             write acc, pos and the program to a JSON snapshot
+restore <file>          pick up from a snapshot, keeping breakpoints
+";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Breakpoint {
+    Address(usize),
+    Opcode(String),
+}
+
+impl Breakpoint {
+    fn hit(&self, pos: usize, op: &Op) -> bool {
+        match self {
+            Breakpoint::Address(addr) => *addr == pos,
+            Breakpoint::Opcode(name) => name == op.name(),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Address(addr) => write!(f, "address {}", addr),
+            Breakpoint::Opcode(name) => write!(f, "every {}", name),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn parse(s: &str) -> Option<Comparison> {
+        Some(match s {
+            "==" => Comparison::Eq,
+            "!=" => Comparison::Ne,
+            "<" => Comparison::Lt,
+            "<=" => Comparison::Le,
+            ">" => Comparison::Gt,
+            ">=" => Comparison::Ge,
+            _ => return None,
+        })
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+
+    fn holds(&self, left: i64, right: i64) -> bool {
+        match self {
+            Comparison::Eq => left == right,
+            Comparison::Ne => left != right,
+            Comparison::Lt => left < right,
+            Comparison::Le => left <= right,
+            Comparison::Gt => left > right,
+            Comparison::Ge => left >= right,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Watch {
+    Compare(Comparison, i64),
+    Changes,
+}
+
+impl Watch {
+    fn parse(args: &[&str]) -> Result<Watch, String> {
+        match args {
+            ["acc", "changes"] => Ok(Watch::Changes),
+            ["acc", op, value] => {
+                let comparison =
+                    Comparison::parse(op).ok_or_else(|| format!("unknown comparison '{}'", op))?;
+                let value = value
+                    .parse()
+                    .map_err(|_| format!("invalid value '{}'", value))?;
+                Ok(Watch::Compare(comparison, value))
+            }
+            _ => Err("expected 'watch acc <op> <value>' or 'watch acc changes'".to_string()),
+        }
+    }
+
+    // Watches fire when their condition becomes true, not for as long as
+    // it stays true.
+    fn triggered(&self, before: i64, after: i64) -> bool {
+        match self {
+            Watch::Changes => before != after,
+            Watch::Compare(comparison, value) => {
+                !comparison.holds(before, *value) && comparison.holds(after, *value)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Watch::Changes => write!(f, "acc changes"),
+            Watch::Compare(comparison, value) => write!(f, "acc {} {}", comparison.symbol(), value),
+        }
+    }
+}
+
+/// Wraps a `Cpu` with breakpoints, watches, per-instruction execution
+/// counts and a history of every step so they can be undone. Everything
+/// is driven by text commands, so a session can be replayed from a file.
+pub struct Debugger {
+    program: Vec<Op>,
+    cpu: Cpu,
+    breakpoints: Vec<Breakpoint>,
+    watches: Vec<Watch>,
+    counts: Vec<u64>,
+    /// How to undo each step, oldest first. Only the last
+    /// `history_limit` are kept, so a long run can't use up memory.
+    history: VecDeque<Undo>,
+    history_limit: usize,
+    /// Whether steps have been dropped from the front of `history`.
+    forgotten: bool,
+    halted: Option<ExitReason>,
+}
+
+impl Debugger {
+    pub fn new(program: Vec<Op>) -> Debugger {
+        Debugger {
+            counts: vec![0; program.len()],
+            cpu: Cpu::new(program.clone()),
+            program,
+            breakpoints: Vec::new(),
+            watches: Vec::new(),
+            history: VecDeque::new(),
+            history_limit: MAX_STATES,
+            forgotten: false,
+            halted: None,
+        }
+    }
+
+    fn start_from(&mut self, cpu: Cpu) {
+        self.program = cpu.program.clone();
+        self.counts = vec![0; self.program.len()];
+        self.history.clear();
+        self.forgotten = false;
+        self.halted = None;
+        self.cpu = cpu;
+    }
+
+    /// Runs one instruction, reporting anything that should stop execution
+    /// and returning whether it can carry on.
+    fn step_once(&mut self, out: &mut String) -> bool {
+        if let Some(reason) = self.halted {
+            writeln!(out, "program has stopped: {}", reason).unwrap();
+            return false;
+        }
+        let (pos, before) = (self.cpu.pos, self.cpu.acc);
+        let undo = self.cpu.undo_point();
+        if let Some(reason) = self.cpu.step() {
+            self.halted = Some(reason);
+            writeln!(out, "program {}, acc {}", reason, self.cpu.acc).unwrap();
+            return false;
+        }
+        self.counts[pos] += 1;
+        self.history.push_back(undo);
+        if self.history.len() > self.history_limit {
+            self.history.pop_front();
+            self.forgotten = true;
+        }
+        if let Op::Out = self.program[pos] {
+            writeln!(out, "out {}", self.cpu.acc).unwrap();
+        }
+        let mut keep_going = true;
+        for (idx, watch) in self.watches.iter().enumerate() {
+            if watch.triggered(before, self.cpu.acc) {
+                writeln!(
+                    out,
+                    "watch {} ({}): acc {} -> {} at {}",
+                    idx, watch, before, self.cpu.acc, pos
+                )
+                .unwrap();
+                keep_going = false;
+            }
+        }
+        if self.cpu.pos == self.program.len() {
+            self.halted = Some(ExitReason::Terminated);
+            writeln!(out, "program terminated, acc {}", self.cpu.acc).unwrap();
+            return false;
+        }
+        keep_going
+    }
+
+    fn continue_running(&mut self, out: &mut String) {
+        // a v1 program loops as soon as it comes back to an instruction,
+        // however it got there; anything else has to repeat its whole
+        // state during this run
+        let mut detector = match Isa::required(&self.program) {
+            Isa::V1 => None,
+            _ => Some(LoopDetector::new(&self.program)),
+        };
+        let mut first = true;
+        loop {
+            let pos = self.cpu.pos;
+            if self.halted.is_none() {
+                if !first {
+                    let op = &self.program[pos];
+                    if let Some(idx) = self.breakpoints.iter().position(|b| b.hit(pos, op)) {
+                        writeln!(out, "breakpoint {} at {}: {}", idx, pos, op).unwrap();
+                        return;
+                    }
+                }
+                let stop = match &mut detector {
+                    Some(detector) => detector.check(&self.cpu),
+                    None if !first && self.counts[pos] > 0 => Some(ExitReason::InfiniteLoop {
+                        at: pos,
+                        acc: self.cpu.acc,
+                    }),
+                    None => None,
+                };
+                if let Some(reason) = stop {
+                    writeln!(out, "{}", reason).unwrap();
+                    return;
+                }
+            }
+            first = false;
+            if !self.step_once(out) {
+                return;
+            }
+        }
+    }
+
+    /// Undoes the last step, if there is one.
+    fn step_back(&mut self) -> bool {
+        match self.history.pop_back() {
+            Some(undo) => {
+                self.cpu.undo(undo);
+                self.counts[self.cpu.pos] -= 1;
+                self.halted = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// What to say when there's nothing left to undo.
+    fn history_start(&self) -> &'static str {
+        if self.forgotten {
+            "at the oldest step kept, earlier ones have been dropped"
+        } else {
+            "at the start of the history"
+        }
+    }
+
+    fn reverse_continue(&mut self, out: &mut String) {
+        while self.step_back() {
+            let pos = self.cpu.pos;
+            let op = &self.program[pos];
+            if let Some(idx) = self.breakpoints.iter().position(|b| b.hit(pos, op)) {
+                writeln!(out, "breakpoint {} at {}: {}", idx, pos, op).unwrap();
+                return;
+            }
+        }
+        writeln!(out, "{}", self.history_start()).unwrap();
+    }
+
+    fn list(&self, radius: usize, out: &mut String) {
+        let pos = self.cpu.pos;
+        let from = pos.saturating_sub(radius);
+        let to = (pos + radius + 1).min(self.program.len());
+        for addr in from..to {
+            let op = &self.program[addr];
+            let marker = if addr == pos { "=>" } else { "  " };
+            let breakpoint = if self.breakpoints.iter().any(|b| b.hit(addr, op)) {
+                "*"
+            } else {
+                " "
+            };
+            writeln!(out, "{}{}{:>5}: {}", marker, breakpoint, addr, op).unwrap();
+        }
+        if pos == self.program.len() {
+            writeln!(out, "=> {:>5}: <end>", pos).unwrap();
+        }
+    }
+
+    /// Runs one debugger command and returns what it printed.
+    pub fn execute(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let mut out = String::new();
+        let number = |s: &str| -> Result<usize, String> {
+            s.parse()
+                .map_err(|_| format!("expected a number, not '{}'", s))
+        };
+        match words.as_slice() {
+            [] => {}
+            ["break", target] => {
+                let breakpoint = match *target {
+                    name if Op::OPCODES.contains(&name) => Breakpoint::Opcode(name.to_string()),
+                    _ => Breakpoint::Address(number(target)?),
+                };
+                writeln!(out, "breakpoint {}: {}", self.breakpoints.len(), breakpoint).unwrap();
+                self.breakpoints.push(breakpoint);
+            }
+            ["delete", n] => {
+                let n = number(n)?;
+                if n >= self.breakpoints.len() {
+                    return Err(format!("no breakpoint {}", n));
+                }
+                self.breakpoints.remove(n);
+            }
+            ["watch", args @ ..] => {
+                let watch = Watch::parse(args)?;
+                writeln!(out, "watch {}: {}", self.watches.len(), watch).unwrap();
+                self.watches.push(watch);
+            }
+            ["unwatch", n] => {
+                let n = number(n)?;
+                if n >= self.watches.len() {
+                    return Err(format!("no watch {}", n));
+                }
+                self.watches.remove(n);
+            }
+            ["step"] => {
+                self.step_once(&mut out);
+            }
+            ["step", n] => {
+                for _ in 0..number(n)? {
+                    if !self.step_once(&mut out) {
+                        break;
+                    }
+                }
+            }
+            ["continue"] => self.continue_running(&mut out),
+            ["back"] => {
+                if !self.step_back() {
+                    writeln!(out, "{}", self.history_start()).unwrap();
+                }
+            }
+            ["back", n] => {
+                for _ in 0..number(n)? {
+                    if !self.step_back() {
+                        writeln!(out, "{}", self.history_start()).unwrap();
+                        break;
+                    }
+                }
+            }
+            ["rcontinue"] => self.reverse_continue(&mut out),
+            ["list"] => self.list(3, &mut out),
+            ["list", radius] => self.list(number(radius)?, &mut out),
+            ["counts"] => {
+                for (addr, count) in self.counts.iter().enumerate() {
+                    if *count > 0 {
+                        writeln!(
+                            out,
+                            "{:>5}: {:<8} x{}",
+                            addr,
+                            self.program[addr].to_string(),
+                            count
+                        )
+                        .unwrap();
+                    }
+                }
+            }
+            ["info"] => {
+                writeln!(
+                    out,
+                    "pos {}, acc {}, {} steps",
+                    self.cpu.pos, self.cpu.acc, self.cpu.steps
+                )
+                .unwrap();
+                if Isa::required(&self.program) > Isa::V1 {
+                    let registers: Vec<String> = self
+                        .cpu
+                        .registers
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, value)| format!("r{} {}", idx, value))
+                        .collect();
+                    writeln!(
+                        out,
+                        "{}, stack {:?}, output {:?}",
+                        registers.join(", "),
+                        self.cpu.stack,
+                        self.cpu.output
+                    )
+                    .unwrap();
+                }
+                for (idx, b) in self.breakpoints.iter().enumerate() {
+                    writeln!(out, "breakpoint {}: {}", idx, b).unwrap();
+                }
+                for (idx, w) in self.watches.iter().enumerate() {
+                    writeln!(out, "watch {}: {}", idx, w).unwrap();
+                }
+            }
+            ["reset"] => self.start_from(Cpu::new(self.program.clone())),
+            ["save", path] => {
+                let json = serde_json::to_string_pretty(&self.cpu.snapshot()).unwrap();
+                fs::write(path, json).map_err(|e| format!("could not write {}: {}", path, e))?;
+                writeln!(
+                    out,
+                    "saved pos {}, acc {} to {}",
+                    self.cpu.pos, self.cpu.acc, path
+                )
+                .unwrap();
+            }
+            ["restore", path] => {
+                let json = fs::read_to_string(path)
+                    .map_err(|e| format!("could not read {}: {}", path, e))?;
+                let snapshot: Snapshot = serde_json::from_str(&json)
+                    .map_err(|e| format!("invalid snapshot {}: {}", path, e))?;
+                if snapshot.pos > snapshot.program.len() {
+                    return Err(format!(
+                        "snapshot pos {} is outside its program",
+                        snapshot.pos
+                    ));
+                }
+                self.start_from(Cpu::from_snapshot(snapshot));
+                writeln!(out, "restored pos {}, acc {}", self.cpu.pos, self.cpu.acc).unwrap();
+            }
+            ["help"] => out.push_str(HELP),
+            _ => return Err(format!("unknown command '{}', try help", line.trim())),
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::{sample_program, Isa, Op};
+    use crate::debugger::Debugger;
+
+    fn sample() -> Debugger {
+        Debugger::new(sample_program())
+    }
+
+    fn run(debugger: &mut Debugger, script: &str) -> String {
+        script
+            .lines()
+            .map(|l| debugger.execute(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut d = sample();
+        assert_eq!(
+            concat!(
+                "breakpoint 0: address 4\n",
+                "breakpoint 1: every jmp\n",
+                "breakpoint 1 at 2: jmp +4\n",
+                "breakpoint 1 at 7: jmp -4\n",
+                "breakpoint 0 at 4: jmp -3\n",
+                "infinite loop at 1 with acc 5\n",
+            ),
+            run(
+                &mut d,
+                "break 4\nbreak jmp\ncontinue\ncontinue\ncontinue\ndelete 1\ncontinue"
+            )
+        );
+        assert_eq!(
+            concat!(
+                "    0: nop +0   x1\n",
+                "    1: acc +1   x1\n",
+                "    2: jmp +4   x1\n",
+                "    3: acc +3   x1\n",
+                "    4: jmp -3   x1\n",
+                "    6: acc +1   x1\n",
+                "    7: jmp -4   x1\n",
+            ),
+            d.execute("counts").unwrap()
+        );
+        assert_eq!(
+            concat!(
+                "       0: nop +0\n",
+                "=>     1: acc +1\n",
+                "       2: jmp +4\n",
+            ),
+            d.execute("list 1").unwrap()
+        );
+        assert_eq!(
+            "pos 1, acc 5, 7 steps\nbreakpoint 0: address 4\n",
+            d.execute("info").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_watches_and_steps() {
+        let mut d = sample();
+        assert_eq!(
+            concat!(
+                "watch 0: acc >= 2\n",
+                "watch 0 (acc >= 2): acc 1 -> 2 at 6\n",
+            ),
+            run(&mut d, "watch acc >= 2\ncontinue")
+        );
+        assert_eq!(
+            concat!(
+                "watch 0: acc changes\n",
+                "watch 0 (acc changes): acc 2 -> 5 at 3\n",
+                "watch 0 (acc changes): acc 5 -> 6 at 1\n",
+            ),
+            run(&mut d, "unwatch 0\nwatch acc changes\nstep 100\nstep 100")
+        );
+
+        // a program that runs off the end stops the debugger for good
+        let mut d = Debugger::new(vec![Op::Acc(2), Op::Nop(0)]);
+        assert_eq!("program terminated, acc 2\n", d.execute("step 5").unwrap());
+        assert_eq!(
+            "program has stopped: terminated\n",
+            d.execute("step").unwrap()
+        );
+        d.execute("reset").unwrap();
+        assert_eq!("", d.execute("step").unwrap());
+
+        let mut d = Debugger::new(vec![Op::Jmp(-1)]);
+        assert_eq!(
+            "program jump from 0 to -1 is outside the program, acc 0\n",
+            d.execute("continue").unwrap()
+        );
+
+        assert!(d.execute("break here").is_err());
+        assert!(d.execute("watch pos > 3").is_err());
+        assert!(d.execute("delete 0").is_err());
+        assert!(d.execute("fly").is_err());
+    }
+
+    #[test]
+    fn test_reverse() {
+        let mut d = sample();
+        run(&mut d, "step 6");
+        assert_eq!("pos 4, acc 5, 6 steps\n", d.execute("info").unwrap());
+        run(&mut d, "back 2");
+        assert_eq!("pos 7, acc 2, 4 steps\n", d.execute("info").unwrap());
+        assert_eq!(
+            "breakpoint 0: address 2\nbreakpoint 0 at 2: jmp +4\n",
+            run(&mut d, "break 2\nrcontinue")
+        );
+        assert_eq!(
+            "at the start of the history\n",
+            run(&mut d, "delete 0\nrcontinue")
+        );
+        assert_eq!(
+            "at the start of the history\n",
+            d.execute("back 3").unwrap()
+        );
+        assert_eq!("pos 0, acc 0, 0 steps\n", d.execute("info").unwrap());
+        assert_eq!("", d.execute("counts").unwrap());
+
+        // undoing the step that ran off the end lets execution carry on
+        let mut d = Debugger::new(vec![Op::Acc(2), Op::Nop(0)]);
+        run(&mut d, "step 2\nback");
+        assert_eq!("pos 1, acc 2, 1 steps\n", d.execute("info").unwrap());
+        assert_eq!("program terminated, acc 2\n", d.execute("step").unwrap());
+
+        // only the newest steps are kept
+        let mut d = sample();
+        d.history_limit = 3;
+        run(&mut d, "step 6");
+        assert_eq!(
+            "at the oldest step kept, earlier ones have been dropped\n",
+            d.execute("back 5").unwrap()
+        );
+        assert_eq!("pos 6, acc 1, 3 steps\n", d.execute("info").unwrap());
+    }
+
+    #[test]
+    fn test_snapshots() {
+        let path = std::env::temp_dir().join(format!("day08-snapshot-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut d = sample();
+        assert_eq!(
+            format!("saved pos 6, acc 1 to {}\n", path),
+            run(&mut d, &format!("step 3\nsave {}", path))
+        );
+        run(&mut d, "step 3");
+        assert_eq!(
+            "restored pos 6, acc 1\n",
+            d.execute(&format!("restore {}", path)).unwrap()
+        );
+        assert_eq!("at the start of the history\n", d.execute("back").unwrap());
+        assert_eq!(
+            "infinite loop at 6 with acc 6\n",
+            d.execute("continue").unwrap()
+        );
+        std::fs::remove_file(path).unwrap();
+        assert!(d.execute(&format!("restore {}", path)).is_err());
+    }
+
+    #[test]
+    fn test_v2() {
+        let program: Vec<Op> = [
+            "acc +2", "sto r1", "call +3", "jnz -1", "jmp +3", "out", "ret",
+        ]
+        .iter()
+        .map(|l| Op::parse_with(l, Isa::V2).unwrap())
+        .collect();
+        let mut d = Debugger::new(program);
+        assert_eq!(
+            concat!(
+                "breakpoint 0: every ret\n",
+                "out 2\n",
+                "breakpoint 0 at 6: ret\n",
+            ),
+            run(&mut d, "break ret\ncontinue")
+        );
+        assert_eq!(
+            concat!(
+                "pos 6, acc 2, 4 steps\n",
+                "r0 0, r1 2, r2 0, r3 0, stack [3], output [2]\n",
+                "breakpoint 0: every ret\n",
+            ),
+            d.execute("info").unwrap()
+        );
+        // acc never changes, so jnz keeps calling the subroutine
+        assert_eq!(
+            "infinite loop at 6 with acc 2\n",
+            run(&mut d, "delete 0\ncontinue")
+                .lines()
+                .last()
+                .map(|l| format!("{}\n", l))
+                .unwrap()
+        );
+        run(&mut d, "back 100");
+        assert_eq!(
+            "pos 0, acc 0, 0 steps\nr0 0, r1 0, r2 0, r3 0, stack [], output []\n",
+            d.execute("info").unwrap()
+        );
+
+        // counting up forever never repeats a state
+        let program: Vec<Op> = ["acc +1", "jmp -1", "out"]
+            .iter()
+            .map(|l| Op::parse_with(l, Isa::V2).unwrap())
+            .collect();
+        let mut d = Debugger::new(program);
+        assert_eq!("step limit reached\n", d.execute("continue").unwrap());
+    }
+}
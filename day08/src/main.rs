@@ -1,105 +1,245 @@
 use std::env;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::process;
 
-#[derive(Clone, Copy, Debug)]
-enum Op {
-    Nop(i32),
-    Acc(i32),
-    Jmp(i32),
+mod asm;
+mod cpu;
+mod debugger;
+mod mutate;
+mod network;
+mod repair;
+mod trace;
+
+use asm::{assemble, disassemble};
+use cpu::{Cpu, ExitReason, Isa, Op};
+use debugger::Debugger;
+use mutate::{search, Goal, SearchOptions, Strategy};
+use network::{Network, Schedule};
+use repair::repair;
+use trace::write_trace;
+
+fn take_option(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let idx = args.iter().position(|a| a == flag)?;
+    if idx + 1 >= args.len() {
+        panic!("{} needs a value", flag);
+    }
+    args.remove(idx);
+    Some(args.remove(idx))
 }
 
-impl Op {
-    fn parse(s: String) -> Op {
-        let ss: Vec<&str> = s.split(" ").collect();
-        let param: i32 = ss.get(1).unwrap().parse().unwrap();
-        let opcode = *ss.get(0).unwrap();
-        match opcode {
-            "nop" => Op::Nop(param),
-            "acc" => Op::Acc(param),
-            "jmp" => Op::Jmp(param),
-            _ => panic!("unsupported opcode"),
+fn load(filename: &str, isa: Isa) -> Vec<Op> {
+    let file = File::open(filename).unwrap();
+    let mut program: Vec<Op> = Vec::new();
+    for (idx, line) in BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .enumerate()
+    {
+        match Op::parse_with(&line, isa) {
+            Ok(op) => program.push(op),
+            Err(e) => {
+                eprintln!("line {}: {}", idx + 1, e);
+                process::exit(1);
+            }
         }
     }
+    program
 }
 
-struct Cpu {
-    acc: i32,
-    pos: usize,
-    program: Vec<Op>,
-}
+/// Reads debugger commands from `--script <file>` if given, otherwise
+/// from stdin with a prompt.
+fn debug(mut args: Vec<String>, isa: Isa) {
+    let script = take_option(&mut args, "--script");
+    let program = load(args.get(2).expect("Specify a file to debug"), isa);
+    let mut debugger = Debugger::new(program);
 
-impl Cpu {
-    fn new(program: Vec<Op>) -> Cpu {
-        Cpu {
-            acc: 0,
-            pos: 0,
-            program,
+    let interactive = script.is_none();
+    let input: Box<dyn BufRead> = match script {
+        Some(path) => Box::new(BufReader::new(File::open(path).unwrap())),
+        None => Box::new(BufReader::new(io::stdin())),
+    };
+    let prompt = || {
+        if interactive {
+            print!("(debug) ");
+            io::stdout().flush().unwrap();
         }
-    }
+    };
 
-    fn step(&mut self) {
-        let op = self.program.get(self.pos).unwrap();
-        self.pos += 1;
-        match op {
-            Op::Acc(value) => self.acc += value,
-            Op::Jmp(value) => self.pos = (self.pos as i32 + value - 1) as usize,
-            _ => {}
+    prompt();
+    for line in input.lines().map_while(Result::ok) {
+        if line.trim() == "quit" {
+            break;
         }
-    }
-
-    fn run(&mut self) -> usize {
-        let mut executed = vec![false; self.program.len()];
-        while self.pos < self.program.len() && !executed[self.pos] {
-            let p = self.pos;
-            self.step();
-            executed[p] = true;
+        if !interactive && !line.trim().is_empty() && !line.starts_with('#') {
+            println!("> {}", line.trim());
         }
-        self.pos
+        if !line.starts_with('#') {
+            match debugger.execute(&line) {
+                Ok(out) => print!("{}", out),
+                Err(e) => {
+                    println!("{}", e);
+                    if !interactive {
+                        process::exit(1);
+                    }
+                }
+            }
+        }
+        prompt();
     }
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    let step_limit: Option<u64> = take_option(&mut args, "--max-steps")
+        .map(|n| n.parse().expect("--max-steps needs a number"));
+    let isa = take_option(&mut args, "--isa")
+        .map(|v| Isa::parse(&v).expect("--isa needs v1, v2 or v3"))
+        .unwrap_or(Isa::V1);
+    let new_cpu = |program: Vec<Op>| match step_limit {
+        Some(limit) => Cpu::with_step_limit(program, limit),
+        None => Cpu::new(program),
+    };
     if args.len() < 2 {
         panic!("Specify a file to run on");
     }
 
-    let file = File::open(args.get(1).unwrap()).unwrap();
-
-    let program: Vec<Op> = BufReader::new(file)
-        .lines()
-        .filter_map(|r| match r {
-            Ok(line) => Some(Op::parse(line)),
-            _ => None,
-        })
-        .collect();
+    match args[1].as_str() {
+        "debug" => return debug(args, isa),
+        "asm" => {
+            let filename = args.get(2).expect("Specify a file to assemble");
+            match assemble(&fs::read_to_string(filename).unwrap()) {
+                Ok(program) => program.iter().for_each(|op| println!("{}", op)),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        "trace" => {
+            let output = take_option(&mut args, "--output");
+            let mut cpu = new_cpu(load(args.get(2).expect("Specify a file to trace"), isa));
+            let reason = match output {
+                Some(path) => {
+                    write_trace(&mut cpu, &mut BufWriter::new(File::create(path).unwrap()))
+                }
+                None => write_trace(&mut cpu, &mut io::stdout().lock()),
+            };
+            match reason {
+                Ok(reason @ ExitReason::InfiniteLoop { .. }) => eprintln!("{}", reason),
+                Ok(reason) => eprintln!("{}, acc {}", reason, cpu.acc),
+                Err(e) => {
+                    eprintln!("could not write trace: {}", e);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        "mutate" => {
+            let mut options = SearchOptions::default();
+            let goal = take_option(&mut args, "--goal")
+                .map(|g| Goal::parse(&g).expect("--goal needs terminate, acc=N or reach=N"))
+                .unwrap_or(Goal::Terminate);
+            if let Some(s) = take_option(&mut args, "--strategy") {
+                options.strategy = Strategy::parse(&s).expect("--strategy needs bfs or heuristic");
+            }
+            if let Some(n) = take_option(&mut args, "--max-edits") {
+                options.max_edits = n.parse().expect("--max-edits needs a number");
+            }
+            if let Some(n) = take_option(&mut args, "--delta") {
+                options.max_delta = n.parse().expect("--delta needs a number");
+            }
+            if let Some(n) = take_option(&mut args, "--max-evaluations") {
+                options.max_evaluations = n.parse().expect("--max-evaluations needs a number");
+            }
+            if let Some(limit) = step_limit {
+                options.step_limit = limit;
+            }
+            let program = load(args.get(2).expect("Specify a file to mutate"), isa);
+            match search(&program, goal, &options) {
+                Ok(solution) => {
+                    for edit in solution.edits.iter() {
+                        println!("{}", edit);
+                    }
+                    println!(
+                        "{} edits, acc {}, {} programs tried",
+                        solution.edits.len(),
+                        solution.acc,
+                        solution.evaluated
+                    );
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        "network" => {
+            let schedule = take_option(&mut args, "--schedule")
+                .map(|s| Schedule::parse(&s).expect("--schedule needs round-robin[=N] or earliest"))
+                .unwrap_or(Schedule::RoundRobin { quantum: 1 });
+            let copies: Option<usize> = take_option(&mut args, "--copies")
+                .map(|n| n.parse().expect("--copies needs a number"));
+            let mut programs: Vec<Vec<Op>> = args[2..].iter().map(|f| load(f, Isa::V3)).collect();
+            if programs.is_empty() {
+                panic!("Specify at least one program to run");
+            }
+            if let Some(n) = copies {
+                programs = programs.iter().cycle().take(n).cloned().collect();
+            }
+            match Network::new(programs) {
+                Ok(mut network) => {
+                    print!("{}", network.run(schedule, step_limit.unwrap_or(1_000_000)))
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        "disasm" => {
+            print!(
+                "{}",
+                disassemble(&load(
+                    args.get(2).expect("Specify a file to disassemble"),
+                    Isa::LATEST
+                ))
+            );
+            return;
+        }
+        _ => {}
+    }
 
-    let mut cpu = Cpu::new(program.clone());
-    cpu.run();
+    let program = load(args.get(1).unwrap(), isa);
 
-    println!("Part 1: acc: {}", cpu.acc);
+    let mut cpu = new_cpu(program.clone());
+    match cpu.run() {
+        ExitReason::InfiniteLoop { .. } => println!("Part 1: acc: {}", cpu.acc),
+        reason => println!("Part 1: no loop, {} with acc {}", reason, cpu.acc),
+    }
+    if !cpu.output.is_empty() {
+        let output: Vec<String> = cpu.output.iter().map(|v| v.to_string()).collect();
+        println!("Output: {}", output.join(","));
+    }
 
-    for i in 0..program.len() {
-        let fixed = match program.get(i).unwrap() {
-            Op::Nop(value) => {
-                let mut fixed = program.clone();
-                fixed[i] = Op::Jmp(*value);
-                fixed
-            }
-            Op::Jmp(value) => {
-                let mut fixed = program.clone();
-                fixed[i] = Op::Nop(*value);
-                fixed
-            }
-            Op::Acc(_) => program.clone(),
-        };
+    if Isa::required(&program) > Isa::V1 {
+        println!("Part 2: repair only handles ISA v1 programs");
+        return;
+    }
 
-        let mut cpu = Cpu::new(fixed);
-        let exit = cpu.run();
-        if exit == program.len() {
-            println!("Part 2: Fixed by modifying pos {} - acc: {}", i, cpu.acc);
-            break;
-        }
+    let result = repair(&program);
+    if result.already_terminates {
+        println!("Part 2: already terminates, nothing to fix");
+    } else if result.flips.is_empty() {
+        println!("Part 2: no single nop/jmp swap fixes the program");
+    }
+    for flip in result.flips.iter() {
+        println!(
+            "Part 2: Fixed by modifying pos {} ({} -> {}) - acc: {}",
+            flip.at, flip.from, flip.to, flip.acc
+        );
     }
 }
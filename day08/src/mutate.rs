@@ -0,0 +1,524 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::fmt;
+
+use crate::cpu::{Cpu, ExitReason, Isa, Op};
+
+/// A change to one instruction. Positions are always in the original
+/// program, however many instructions before them have been deleted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Mutation {
+    /// Replace the opcode, keeping the argument.
+    Swap {
+        at: usize,
+        to: Op,
+    },
+    /// Add `delta` to a numeric argument.
+    Adjust {
+        at: usize,
+        delta: i32,
+    },
+    Delete {
+        at: usize,
+    },
+}
+
+impl Mutation {
+    pub fn at(&self) -> usize {
+        match self {
+            Mutation::Swap { at, .. } | Mutation::Adjust { at, .. } | Mutation::Delete { at } => {
+                *at
+            }
+        }
+    }
+}
+
+impl fmt::Display for Mutation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mutation::Swap { at, to } => write!(f, "change {} to {}", at, to),
+            Mutation::Adjust { at, delta } => write!(f, "adjust {} by {:+}", at, delta),
+            Mutation::Delete { at } => write!(f, "delete {}", at),
+        }
+    }
+}
+
+fn adjusted(op: Op, delta: i32) -> Option<Op> {
+    let add = |value: i32| value.checked_add(delta);
+    Some(match op {
+        Op::Nop(value) => Op::Nop(add(value)?),
+        Op::Acc(value) => Op::Acc(add(value)?),
+        Op::Jmp(value) => Op::Jmp(add(value)?),
+        Op::Jz(value) => Op::Jz(add(value)?),
+        Op::Jnz(value) => Op::Jnz(add(value)?),
+        Op::Jgz(value) => Op::Jgz(add(value)?),
+        Op::Call(value) => Op::Call(add(value)?),
+        Op::Snd(value) => Op::Snd(add(value)?),
+        _ => return None,
+    })
+}
+
+/// Every opcode `op` could be swapped for that takes the same kind of
+/// argument and belongs to `isa`.
+fn swaps(op: Op, isa: Isa) -> Vec<Op> {
+    let text = op.to_string();
+    let argument = &text[op.name().len()..];
+    Op::OPCODES
+        .iter()
+        .filter(|&&name| name != op.name() && Op::operand(name) == Op::operand(op.name()))
+        .filter_map(|name| Op::parse_with(&format!("{}{}", name, argument), isa).ok())
+        .collect()
+}
+
+/// Applies `edits`, returning the new program and, for each of its
+/// instructions, where it was in the original.
+pub fn apply(program: &[Op], edits: &[Mutation]) -> (Vec<Op>, Vec<usize>) {
+    let mut ops: Vec<Option<Op>> = program.iter().map(|&op| Some(op)).collect();
+    for edit in edits {
+        let op = &mut ops[edit.at()];
+        *op = match *edit {
+            Mutation::Swap { to, .. } => Some(to),
+            Mutation::Adjust { delta, .. } => op.and_then(|op| adjusted(op, delta)),
+            Mutation::Delete { .. } => None,
+        };
+    }
+    ops.into_iter()
+        .enumerate()
+        .filter_map(|(idx, op)| Some((op?, idx)))
+        .unzip()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Goal {
+    Terminate,
+    /// Terminate with this acc.
+    Acc(i64),
+    /// Get to this address in the original program; its length means the
+    /// end.
+    Reach(usize),
+}
+
+impl Goal {
+    pub fn parse(s: &str) -> Option<Goal> {
+        match s.split_once('=') {
+            None if s == "terminate" => Some(Goal::Terminate),
+            Some(("acc", value)) => value.parse().ok().map(Goal::Acc),
+            Some(("reach", value)) => value.parse().ok().map(Goal::Reach),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    /// Tries every set of one edit, then two, and so on, so the first
+    /// solution found has as few edits as possible.
+    BreadthFirst,
+    /// Only changes instructions that actually run, trying first whatever
+    /// got closest to the goal. Usually much quicker, but can miss a
+    /// smaller solution.
+    Heuristic,
+}
+
+impl Strategy {
+    pub fn parse(s: &str) -> Option<Strategy> {
+        match s {
+            "bfs" => Some(Strategy::BreadthFirst),
+            "heuristic" => Some(Strategy::Heuristic),
+            _ => None,
+        }
+    }
+}
+
+pub struct SearchOptions {
+    pub strategy: Strategy,
+    pub max_edits: usize,
+    /// Arguments are adjusted by up to this much either way.
+    pub max_delta: i32,
+    /// Runs that go on longer than this count as not terminating.
+    pub step_limit: u64,
+    /// Give up after trying this many programs.
+    pub max_evaluations: usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> SearchOptions {
+        SearchOptions {
+            strategy: Strategy::BreadthFirst,
+            max_edits: 2,
+            max_delta: 1,
+            step_limit: 100_000,
+            max_evaluations: 1_000_000,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Solution {
+    pub edits: Vec<Mutation>,
+    pub program: Vec<Op>,
+    pub acc: i64,
+    /// How many programs were tried.
+    pub evaluated: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SearchFailure {
+    /// Every program within the edit limit was tried.
+    NoSolution { evaluated: usize },
+    /// `max_evaluations` ran out first.
+    GaveUp { evaluated: usize },
+    /// `Goal::Reach` names an address past the end of the program, which
+    /// no edit can create.
+    NoSuchAddress { address: usize, len: usize },
+}
+
+impl fmt::Display for SearchFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SearchFailure::NoSolution { evaluated } => {
+                write!(f, "no solution after trying {} programs", evaluated)
+            }
+            SearchFailure::GaveUp { evaluated } => {
+                write!(f, "gave up after trying {} programs", evaluated)
+            }
+            SearchFailure::NoSuchAddress { address, len } => write!(
+                f,
+                "can't reach {} in a program of {} instructions",
+                address, len
+            ),
+        }
+    }
+}
+
+struct Run {
+    program: Vec<Op>,
+    acc: i64,
+    done: bool,
+    /// How far from the goal the run got; lower is closer.
+    distance: u64,
+    /// Original positions of the instructions that ran.
+    executed: Vec<usize>,
+}
+
+struct Searcher<'a> {
+    program: &'a [Op],
+    goal: Goal,
+    options: &'a SearchOptions,
+    /// Every mutation of each instruction.
+    choices: Vec<Vec<Mutation>>,
+    evaluated: usize,
+}
+
+impl<'a> Searcher<'a> {
+    fn new(program: &'a [Op], goal: Goal, options: &'a SearchOptions) -> Searcher<'a> {
+        let isa = Isa::required(program);
+        let choices = program
+            .iter()
+            .enumerate()
+            .map(|(at, &op)| {
+                let mut choices: Vec<Mutation> = swaps(op, isa)
+                    .into_iter()
+                    .map(|to| Mutation::Swap { at, to })
+                    .collect();
+                for delta in -options.max_delta..=options.max_delta {
+                    if delta != 0 && adjusted(op, delta).is_some() {
+                        choices.push(Mutation::Adjust { at, delta });
+                    }
+                }
+                choices.push(Mutation::Delete { at });
+                choices
+            })
+            .collect();
+        Searcher {
+            program,
+            goal,
+            options,
+            choices,
+            evaluated: 0,
+        }
+    }
+
+    fn evaluate(&mut self, edits: &[Mutation]) -> Result<Run, SearchFailure> {
+        if self.evaluated >= self.options.max_evaluations {
+            return Err(SearchFailure::GaveUp {
+                evaluated: self.evaluated,
+            });
+        }
+        self.evaluated += 1;
+
+        let (program, origins) = apply(self.program, edits);
+        let len = program.len();
+        let target = match self.goal {
+            Goal::Reach(address) if address == self.program.len() => Some(len),
+            Goal::Reach(address) => origins.iter().position(|&o| o == address),
+            _ => None,
+        };
+        let mut executed = Vec::new();
+        let mut closest = target.map_or(u64::MAX, |t| t as u64);
+        let mut furthest = 0;
+        let mut cpu = Cpu::with_step_limit(program, self.options.step_limit);
+        let reason = cpu.run_traced(|entry| {
+            executed.push(origins[entry.pos]);
+            furthest = furthest.max(entry.next);
+            if let Some(t) = target {
+                closest = closest.min(t.abs_diff(entry.next) as u64);
+            }
+        });
+        if cpu.pos < len {
+            // whatever it stopped on is as likely a culprit as anything
+            executed.push(origins[cpu.pos]);
+        }
+        let terminated = reason == ExitReason::Terminated;
+        let (done, distance) = match self.goal {
+            Goal::Terminate => (terminated, (len - furthest) as u64),
+            Goal::Acc(acc) if terminated => (cpu.acc == acc, cpu.acc.abs_diff(acc)),
+            // not terminating at all is worse than the wrong answer
+            Goal::Acc(_) => (false, (1 << 40) + (len - furthest) as u64),
+            Goal::Reach(_) => (closest == 0, closest),
+        };
+        Ok(Run {
+            program: cpu.program,
+            acc: cpu.acc,
+            done,
+            distance,
+            executed,
+        })
+    }
+
+    fn solution(&self, edits: Vec<Mutation>, run: Run) -> Solution {
+        Solution {
+            edits,
+            program: run.program,
+            acc: run.acc,
+            evaluated: self.evaluated,
+        }
+    }
+
+    /// Tries every way of adding `remaining` more edits after position
+    /// `from`, keeping edits in position order so each set is tried once.
+    fn combinations(
+        &mut self,
+        from: usize,
+        remaining: usize,
+        edits: &mut Vec<Mutation>,
+    ) -> Result<Option<Solution>, SearchFailure> {
+        if remaining == 0 {
+            let run = self.evaluate(edits)?;
+            return Ok(if run.done {
+                Some(self.solution(edits.clone(), run))
+            } else {
+                None
+            });
+        }
+        for at in from..self.program.len() {
+            for idx in 0..self.choices[at].len() {
+                edits.push(self.choices[at][idx]);
+                let found = self.combinations(at + 1, remaining - 1, edits)?;
+                edits.pop();
+                if found.is_some() {
+                    return Ok(found);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn breadth_first(&mut self) -> Result<Solution, SearchFailure> {
+        for size in 0..=self.options.max_edits {
+            if let Some(solution) = self.combinations(0, size, &mut Vec::new())? {
+                return Ok(solution);
+            }
+        }
+        Err(SearchFailure::NoSolution {
+            evaluated: self.evaluated,
+        })
+    }
+
+    fn heuristic(&mut self) -> Result<Solution, SearchFailure> {
+        let mut queue = BinaryHeap::new();
+        let mut seen: HashSet<Vec<Mutation>> = HashSet::new();
+        let start = self.evaluate(&[])?;
+        if start.done {
+            return Ok(self.solution(Vec::new(), start));
+        }
+        queue.push(Reverse((start.distance, Vec::new(), start.executed)));
+
+        while let Some(Reverse((_, edits, executed))) = queue.pop() {
+            if edits.len() == self.options.max_edits {
+                continue;
+            }
+            // changing an instruction that never ran can only matter
+            // through deletes shifting everything after it
+            let mut positions: Vec<usize> = executed;
+            positions.sort_unstable();
+            positions.dedup();
+            for at in positions {
+                if edits.iter().any(|e: &Mutation| e.at() == at) {
+                    continue;
+                }
+                for idx in 0..self.choices[at].len() {
+                    let mut next = edits.clone();
+                    next.push(self.choices[at][idx]);
+                    next.sort();
+                    if !seen.insert(next.clone()) {
+                        continue;
+                    }
+                    let run = self.evaluate(&next)?;
+                    if run.done {
+                        return Ok(self.solution(next, run));
+                    }
+                    queue.push(Reverse((run.distance, next, run.executed)));
+                }
+            }
+        }
+        Err(SearchFailure::NoSolution {
+            evaluated: self.evaluated,
+        })
+    }
+}
+
+/// Looks for a set of edits, at most one per instruction, that makes
+/// `program` meet `goal`.
+pub fn search(
+    program: &[Op],
+    goal: Goal,
+    options: &SearchOptions,
+) -> Result<Solution, SearchFailure> {
+    if let Goal::Reach(address) = goal {
+        if address > program.len() {
+            return Err(SearchFailure::NoSuchAddress {
+                address,
+                len: program.len(),
+            });
+        }
+    }
+    let mut searcher = Searcher::new(program, goal, options);
+    match options.strategy {
+        Strategy::BreadthFirst => searcher.breadth_first(),
+        Strategy::Heuristic => searcher.heuristic(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::{sample_program, Cpu, ExitReason, Op};
+    use crate::mutate::{apply, search, Goal, Mutation, SearchFailure, SearchOptions, Strategy};
+
+    #[test]
+    fn test_apply() {
+        let edits = [
+            Mutation::Swap {
+                at: 0,
+                to: Op::Jmp(0),
+            },
+            Mutation::Adjust { at: 2, delta: -1 },
+            Mutation::Delete { at: 3 },
+            Mutation::Delete { at: 5 },
+        ];
+        let (program, origins) = apply(&sample_program(), &edits);
+        assert_eq!(
+            vec![
+                Op::Jmp(0),
+                Op::Acc(1),
+                Op::Jmp(3),
+                Op::Jmp(-3),
+                Op::Acc(1),
+                Op::Jmp(-4),
+                Op::Acc(6)
+            ],
+            program
+        );
+        assert_eq!(vec![0, 1, 2, 4, 6, 7, 8], origins);
+        assert_eq!("change 0 to jmp +0", edits[0].to_string());
+        assert_eq!("adjust 2 by -1", edits[1].to_string());
+    }
+
+    #[test]
+    fn test_breadth_first() {
+        let options = SearchOptions::default();
+        let solution = search(&sample_program(), Goal::Terminate, &options).unwrap();
+        assert_eq!(1, solution.edits.len());
+        let mut cpu = Cpu::new(solution.program.clone());
+        assert_eq!(ExitReason::Terminated, cpu.run());
+        assert_eq!(solution.acc, cpu.acc);
+
+        let solution = search(&sample_program(), Goal::Acc(8), &options).unwrap();
+        assert_eq!(
+            vec![Mutation::Swap {
+                at: 7,
+                to: Op::Nop(-4)
+            }],
+            solution.edits
+        );
+
+        // acc +6 only runs if the program gets to it
+        let solution = search(&sample_program(), Goal::Reach(8), &options).unwrap();
+        assert_eq!(1, solution.edits.len());
+
+        // nothing to do
+        let solution = search(&sample_program(), Goal::Reach(4), &options).unwrap();
+        assert!(solution.edits.is_empty());
+        assert_eq!(1, solution.evaluated);
+
+        // just past the end means terminating, anything further is an error
+        // before any programs are tried
+        assert!(search(&sample_program(), Goal::Reach(9), &options).is_ok());
+        assert_eq!(
+            Err(SearchFailure::NoSuchAddress {
+                address: 10,
+                len: 9
+            }),
+            search(&sample_program(), Goal::Reach(10), &options).map(|s| s.evaluated)
+        );
+    }
+
+    #[test]
+    fn test_needs_two_edits() {
+        let program = vec![Op::Nop(0), Op::Jmp(0), Op::Jmp(0)];
+        let one = SearchOptions {
+            max_edits: 1,
+            ..SearchOptions::default()
+        };
+        assert!(matches!(
+            search(&program, Goal::Terminate, &one),
+            Err(SearchFailure::NoSolution { .. })
+        ));
+        for strategy in [Strategy::BreadthFirst, Strategy::Heuristic].iter() {
+            let options = SearchOptions {
+                strategy: *strategy,
+                ..SearchOptions::default()
+            };
+            let solution = search(&program, Goal::Terminate, &options).unwrap();
+            assert_eq!(2, solution.edits.len());
+            assert_eq!(ExitReason::Terminated, Cpu::new(solution.program).run());
+        }
+
+        let tiny = SearchOptions {
+            max_evaluations: 5,
+            ..SearchOptions::default()
+        };
+        assert_eq!(
+            Err(SearchFailure::GaveUp { evaluated: 5 }),
+            search(&program, Goal::Terminate, &tiny)
+        );
+    }
+
+    #[test]
+    fn test_heuristic() {
+        let options = SearchOptions {
+            strategy: Strategy::Heuristic,
+            ..SearchOptions::default()
+        };
+        let solution = search(&sample_program(), Goal::Acc(8), &options).unwrap();
+        let mut cpu = Cpu::new(solution.program.clone());
+        assert_eq!(ExitReason::Terminated, cpu.run());
+        assert_eq!(8, cpu.acc);
+        assert!(solution.edits.len() <= 2);
+
+        assert_eq!(Some(Goal::Acc(-3)), Goal::parse("acc=-3"));
+        assert_eq!(Some(Goal::Reach(4)), Goal::parse("reach=4"));
+        assert_eq!(Some(Goal::Terminate), Goal::parse("terminate"));
+        assert_eq!(None, Goal::parse("acc"));
+    }
+}
@@ -0,0 +1,336 @@
+use std::fmt;
+
+use crate::cpu::{Cpu, ExitReason, Op};
+
+/// Which CPU runs next.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Schedule {
+    /// Each CPU in turn runs up to `quantum` instructions, or until it has
+    /// to wait for a value.
+    RoundRobin { quantum: u64 },
+    /// Always runs one instruction on whichever CPU has run the fewest,
+    /// lowest id first, as if they all ran at the same speed.
+    EarliestFirst,
+}
+
+impl Schedule {
+    pub fn parse(s: &str) -> Option<Schedule> {
+        match s.split_once('=') {
+            None if s == "round-robin" => Some(Schedule::RoundRobin { quantum: 1 }),
+            None if s == "earliest" => Some(Schedule::EarliestFirst),
+            Some(("round-robin", quantum)) => quantum
+                .parse()
+                .ok()
+                .filter(|&q| q > 0)
+                .map(|quantum| Schedule::RoundRobin { quantum }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum NetworkError {
+    /// `snd` at `at` in CPU `cpu` names a CPU that doesn't exist.
+    UnknownCpu { cpu: usize, at: usize, target: i32 },
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::UnknownCpu { cpu, at, target } => write!(
+                f,
+                "cpu {} sends to cpu {} at {}, which doesn't exist",
+                cpu, target, at
+            ),
+        }
+    }
+}
+
+pub struct CpuReport {
+    pub id: usize,
+    pub reason: ExitReason,
+    pub acc: i64,
+    pub steps: u64,
+    pub sent: u64,
+    pub received: u64,
+    pub output: Vec<i64>,
+}
+
+impl fmt::Display for CpuReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cpu {}: {}, acc {}, {} steps, sent {}, received {}",
+            self.id, self.reason, self.acc, self.steps, self.sent, self.received
+        )?;
+        if !self.output.is_empty() {
+            let output: Vec<String> = self.output.iter().map(|v| v.to_string()).collect();
+            write!(f, ", output {}", output.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+pub struct NetworkReport {
+    pub cpus: Vec<CpuReport>,
+    /// Every CPU still running was waiting on another.
+    pub deadlock: bool,
+    pub steps: u64,
+}
+
+impl fmt::Display for NetworkReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for cpu in self.cpus.iter() {
+            writeln!(f, "{}", cpu)?;
+        }
+        if self.deadlock {
+            writeln!(f, "deadlock after {} steps", self.steps)
+        } else {
+            writeln!(f, "stopped after {} steps", self.steps)
+        }
+    }
+}
+
+/// CPUs that can `snd` values to each other, identified by their position.
+/// Each starts with its id in r0, so copies of one program can tell
+/// themselves apart.
+pub struct Network {
+    cpus: Vec<Cpu>,
+    halted: Vec<Option<ExitReason>>,
+    blocked: Vec<bool>,
+    sent: Vec<u64>,
+    received: Vec<u64>,
+    steps: u64,
+}
+
+impl Network {
+    pub fn new(programs: Vec<Vec<Op>>) -> Result<Network, NetworkError> {
+        let count = programs.len();
+        for (cpu, program) in programs.iter().enumerate() {
+            for (at, op) in program.iter().enumerate() {
+                if let Op::Snd(target) = *op {
+                    if target < 0 || target as usize >= count {
+                        return Err(NetworkError::UnknownCpu { cpu, at, target });
+                    }
+                }
+            }
+        }
+        let cpus: Vec<Cpu> = programs
+            .into_iter()
+            .enumerate()
+            .map(|(id, program)| {
+                let mut cpu = Cpu::new(program);
+                cpu.registers[0] = id as i64;
+                cpu
+            })
+            .collect();
+        Ok(Network {
+            halted: vec![None; count],
+            blocked: vec![false; count],
+            sent: vec![0; count],
+            received: vec![0; count],
+            steps: 0,
+            cpus,
+        })
+    }
+
+    // a blocked CPU can go again once something has been sent to it
+    fn runnable(&self, id: usize) -> bool {
+        self.halted[id].is_none() && !(self.blocked[id] && self.cpus[id].inbox.is_empty())
+    }
+
+    /// Runs one instruction on CPU `id` and delivers anything it sent.
+    /// Returns whether it can carry on.
+    fn step(&mut self, id: usize) -> bool {
+        let cpu = &mut self.cpus[id];
+        let receiving = cpu.program.get(cpu.pos) == Some(&Op::Rcv);
+        match cpu.step() {
+            None => {
+                self.steps += 1;
+                self.blocked[id] = false;
+                if receiving {
+                    self.received[id] += 1;
+                }
+                for (target, value) in std::mem::take(&mut self.cpus[id].outbox) {
+                    self.cpus[target as usize].inbox.push_back(value);
+                    self.sent[id] += 1;
+                }
+                true
+            }
+            Some(ExitReason::Blocked { .. }) => {
+                self.blocked[id] = true;
+                false
+            }
+            Some(reason) => {
+                self.halted[id] = Some(reason);
+                false
+            }
+        }
+    }
+
+    /// Runs until every CPU has stopped, they're all waiting on each
+    /// other, or `step_limit` instructions have run between them.
+    pub fn run(&mut self, schedule: Schedule, step_limit: u64) -> NetworkReport {
+        let count = self.cpus.len();
+        let mut next = 0;
+        let mut deadlock = false;
+        loop {
+            if self.halted.iter().all(Option::is_some) {
+                break;
+            }
+            if self.steps >= step_limit {
+                for reason in self.halted.iter_mut().filter(|r| r.is_none()) {
+                    *reason = Some(ExitReason::StepLimit);
+                }
+                break;
+            }
+            let chosen = match schedule {
+                Schedule::RoundRobin { .. } => (0..count)
+                    .map(|offset| (next + offset) % count)
+                    .find(|&id| self.runnable(id)),
+                Schedule::EarliestFirst => (0..count)
+                    .filter(|&id| self.runnable(id))
+                    .min_by_key(|&id| self.cpus[id].steps),
+            };
+            let id = match chosen {
+                Some(id) => id,
+                None => {
+                    deadlock = true;
+                    for (id, reason) in self.halted.iter_mut().enumerate() {
+                        if reason.is_none() {
+                            *reason = Some(ExitReason::Blocked {
+                                at: self.cpus[id].pos,
+                            });
+                        }
+                    }
+                    break;
+                }
+            };
+            match schedule {
+                Schedule::RoundRobin { quantum } => {
+                    for _ in 0..quantum {
+                        if self.steps >= step_limit || !self.step(id) {
+                            break;
+                        }
+                    }
+                    next = (id + 1) % count;
+                }
+                Schedule::EarliestFirst => {
+                    self.step(id);
+                }
+            }
+        }
+
+        NetworkReport {
+            cpus: self
+                .cpus
+                .iter()
+                .enumerate()
+                .map(|(id, cpu)| CpuReport {
+                    id,
+                    reason: self.halted[id].unwrap(),
+                    acc: cpu.acc,
+                    steps: cpu.steps,
+                    sent: self.sent[id],
+                    received: self.received[id],
+                    output: cpu.output.clone(),
+                })
+                .collect(),
+            deadlock,
+            steps: self.steps,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::{ExitReason, Isa, Op};
+    use crate::network::{Network, NetworkError, Schedule};
+
+    fn program(source: &str) -> Vec<Op> {
+        source
+            .lines()
+            .map(|l| Op::parse_with(l, Isa::V3).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_ping_pong() {
+        let mut network = Network::new(vec![
+            program("acc +5\nsnd +1\nrcv\nout"),
+            program("rcv\nacc +1\nsnd +0"),
+        ])
+        .unwrap();
+        let report = network.run(Schedule::RoundRobin { quantum: 1 }, 1000);
+        assert!(!report.deadlock);
+        assert_eq!(
+            concat!(
+                "cpu 0: terminated, acc 6, 4 steps, sent 1, received 1, output 6\n",
+                "cpu 1: terminated, acc 6, 3 steps, sent 1, received 1\n",
+                "stopped after 7 steps\n",
+            ),
+            report.to_string()
+        );
+    }
+
+    #[test]
+    fn test_copies_know_their_id() {
+        let source = "lod r0\njnz +4\nacc +10\nsnd +1\njmp +2\nsnd +0\nrcv\nout";
+        for schedule in [Schedule::RoundRobin { quantum: 3 }, Schedule::EarliestFirst].iter() {
+            let mut network = Network::new(vec![program(source), program(source)]).unwrap();
+            let report = network.run(*schedule, 1000);
+            assert_eq!(vec![1], report.cpus[0].output);
+            assert_eq!(vec![10], report.cpus[1].output);
+        }
+    }
+
+    #[test]
+    fn test_schedules() {
+        let programs = || {
+            vec![
+                program("nop +0\nnop +0\nacc +1\nsnd +2"),
+                program("acc +2\nsnd +2"),
+                program("rcv\nout\nrcv\nout"),
+            ]
+        };
+        let run = |schedule| {
+            Network::new(programs()).unwrap().run(schedule, 1000).cpus[2]
+                .output
+                .clone()
+        };
+        assert_eq!(vec![1, 2], run(Schedule::RoundRobin { quantum: 10 }));
+        assert_eq!(vec![2, 1], run(Schedule::EarliestFirst));
+    }
+
+    #[test]
+    fn test_deadlock_and_limits() {
+        let mut network =
+            Network::new(vec![program("rcv\nsnd +1"), program("acc +1\nrcv\nsnd +0")]).unwrap();
+        let report = network.run(Schedule::EarliestFirst, 1000);
+        assert!(report.deadlock);
+        assert_eq!(ExitReason::Blocked { at: 0 }, report.cpus[0].reason);
+        assert_eq!(ExitReason::Blocked { at: 1 }, report.cpus[1].reason);
+        assert!(report.to_string().ends_with("deadlock after 1 steps\n"));
+
+        let mut network = Network::new(vec![program("jmp +0"), program("acc +1")]).unwrap();
+        let report = network.run(Schedule::RoundRobin { quantum: 4 }, 50);
+        assert!(!report.deadlock);
+        assert_eq!(ExitReason::StepLimit, report.cpus[0].reason);
+        assert_eq!(ExitReason::Terminated, report.cpus[1].reason);
+        assert_eq!(50, report.steps);
+
+        assert_eq!(
+            Err(NetworkError::UnknownCpu {
+                cpu: 1,
+                at: 0,
+                target: 2
+            }),
+            Network::new(vec![program("nop +0"), program("snd +2")]).map(|_| ())
+        );
+        assert_eq!(
+            Some(Schedule::RoundRobin { quantum: 8 }),
+            Schedule::parse("round-robin=8")
+        );
+        assert_eq!(None, Schedule::parse("round-robin=0"));
+    }
+}
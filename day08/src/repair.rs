@@ -0,0 +1,178 @@
+use crate::cpu::Op;
+
+/// Swapping the instruction at `at` for `to` makes the program terminate
+/// with `acc`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Flip {
+    pub at: usize,
+    pub from: Op,
+    pub to: Op,
+    pub acc: i64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Repair {
+    pub already_terminates: bool,
+    pub flips: Vec<Flip>,
+}
+
+fn flipped(op: Op) -> Option<Op> {
+    match op {
+        Op::Nop(value) => Some(Op::Jmp(value)),
+        Op::Jmp(value) => Some(Op::Nop(value)),
+        _ => None,
+    }
+}
+
+fn acc_change(op: Op) -> i64 {
+    match op {
+        Op::Acc(value) => value as i64,
+        _ => 0,
+    }
+}
+
+/// Where execution goes after `op` at `pos`, with `len` meaning the end of
+/// the program. `None` for jumps that fault.
+fn successor(pos: usize, op: Op, len: usize) -> Option<usize> {
+    let next = match op {
+        Op::Jmp(value) => pos as i64 + value as i64,
+        _ => pos as i64 + 1,
+    };
+    if next < 0 || next > len as i64 {
+        None
+    } else {
+        Some(next as usize)
+    }
+}
+
+/// Finds every single nop/jmp swap that lets an ISA v1 program run off the
+/// end, without re-running it for each candidate.
+///
+/// Each instruction has exactly one successor, so working backwards from
+/// the end gives the set of instructions that terminate, along with the
+/// acc they add on the way. The path the unmodified program takes from 0
+/// can then be checked one instruction at a time: a swap works if it sends
+/// execution into that set.
+pub fn repair(program: &[Op]) -> Repair {
+    let len = program.len();
+    let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); len + 1];
+    for (pos, &op) in program.iter().enumerate() {
+        if let Some(next) = successor(pos, op, len) {
+            predecessors[next].push(pos);
+        }
+    }
+
+    // acc_to_end[pos] is what running from pos adds to acc before the
+    // program ends, for every pos that ends at all
+    let mut acc_to_end: Vec<Option<i64>> = vec![None; len + 1];
+    acc_to_end[len] = Some(0);
+    let mut queue = vec![len];
+    while let Some(node) = queue.pop() {
+        for &pred in predecessors[node].iter() {
+            acc_to_end[pred] = Some(acc_change(program[pred]) + acc_to_end[node].unwrap());
+            queue.push(pred);
+        }
+    }
+
+    let mut flips = Vec::new();
+    let mut visited = vec![false; len];
+    let mut acc: i64 = 0;
+    let mut pos = 0;
+    while pos < len && !visited[pos] {
+        visited[pos] = true;
+        let op = program[pos];
+        // a swap somewhere that already ends would just send execution
+        // round whatever loop we were avoiding
+        if acc_to_end[pos].is_none() {
+            if let Some(to) = flipped(op) {
+                if let Some(rest) = successor(pos, to, len).and_then(|next| acc_to_end[next]) {
+                    flips.push(Flip {
+                        at: pos,
+                        from: op,
+                        to,
+                        acc: acc + rest,
+                    });
+                }
+            }
+        }
+        acc += acc_change(op);
+        pos = match successor(pos, op, len) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    flips.sort_by_key(|flip| flip.at);
+    Repair {
+        already_terminates: len == 0 || acc_to_end[0].is_some(),
+        flips,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::{sample_program, Cpu, ExitReason, Op};
+    use crate::repair::{flipped, repair, Flip};
+
+    fn brute_force(program: &[Op]) -> Vec<(usize, i64)> {
+        (0..program.len())
+            .filter_map(|at| {
+                let mut fixed = program.to_vec();
+                fixed[at] = flipped(fixed[at])?;
+                let mut cpu = Cpu::new(fixed);
+                if cpu.run() == ExitReason::Terminated {
+                    Some((at, cpu.acc))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sample() {
+        let program = sample_program();
+        let result = repair(&program);
+        assert!(!result.already_terminates);
+        assert_eq!(
+            vec![Flip {
+                at: 7,
+                from: Op::Jmp(-4),
+                to: Op::Nop(-4),
+                acc: 8
+            }],
+            result.flips
+        );
+    }
+
+    #[test]
+    fn test_matches_brute_force() {
+        let mut seed: u32 = 0x2545_f491;
+        let mut next = |bound: u32| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed % bound
+        };
+        for _ in 0..500 {
+            let len = 1 + next(12) as i32;
+            let program: Vec<Op> = (0..len)
+                .map(|_| {
+                    let value = next(2 * len as u32 + 3) as i32 - len - 1;
+                    match next(3) {
+                        0 => Op::Nop(value),
+                        1 => Op::Acc(value),
+                        _ => Op::Jmp(value),
+                    }
+                })
+                .collect();
+            let result = repair(&program);
+            let terminates = Cpu::new(program.clone()).run() == ExitReason::Terminated;
+            assert_eq!(terminates, result.already_terminates, "{:?}", program);
+            if !terminates {
+                let found: Vec<(usize, i64)> = result.flips.iter().map(|f| (f.at, f.acc)).collect();
+                assert_eq!(brute_force(&program), found, "{:?}", program);
+            }
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::cpu::{Cpu, ExitReason, Op};
+
+/// One instruction the CPU ran: where, what, and acc either side of it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TraceEntry {
+    pub step: u64,
+    pub pos: usize,
+    pub op: Op,
+    pub acc_before: i64,
+    pub acc_after: i64,
+    pub next: usize,
+}
+
+/// Runs `cpu` to completion, writing every step to `out` as a line of JSON.
+pub fn write_trace(cpu: &mut Cpu, out: &mut impl Write) -> io::Result<ExitReason> {
+    let mut result = Ok(());
+    let reason = cpu.run_traced(|entry| {
+        if result.is_ok() {
+            result = serde_json::to_writer(&mut *out, entry)
+                .map_err(io::Error::from)
+                .and_then(|_| writeln!(out));
+        }
+    });
+    result.map(|_| reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::{Cpu, ExitReason, Op};
+    use crate::trace::write_trace;
+
+    #[test]
+    fn test_write_trace() {
+        let mut cpu = Cpu::new(vec![Op::Acc(3), Op::Jmp(2), Op::Acc(-99), Op::Nop(0)]);
+        let mut out = Vec::new();
+        assert_eq!(
+            ExitReason::Terminated,
+            write_trace(&mut cpu, &mut out).unwrap()
+        );
+        assert_eq!(
+            concat!(
+                r#"{"step":0,"pos":0,"op":"acc +3","acc_before":0,"acc_after":3,"next":1}"#,
+                "\n",
+                r#"{"step":1,"pos":1,"op":"jmp +2","acc_before":3,"acc_after":3,"next":3}"#,
+                "\n",
+                r#"{"step":2,"pos":3,"op":"nop +0","acc_before":3,"acc_after":3,"next":4}"#,
+                "\n",
+            ),
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
@@ -1,9 +1,17 @@
+use std::collections::{HashMap, VecDeque};
 use std::env;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    let show_all = match args.iter().position(|a| a == "--all") {
+        Some(idx) => {
+            args.remove(idx);
+            true
+        }
+        None => false,
+    };
     if args.len() < 3 {
         panic!("please specify a file");
     }
@@ -14,22 +22,29 @@ fn main() {
 
     let values: Vec<i64> = BufReader::new(file)
         .lines()
-        .filter_map(|r| match r {
-            Ok(line) => Some(line.parse().unwrap()),
-            _ => None,
-        })
+        .map_while(Result::ok)
+        .map(|line| line.parse().unwrap())
         .collect();
 
-    let invalid = find_invalid_value(preamble_size, values.clone());
-    if invalid <= 0 {
-        panic!("Could not find invalid value!");
-    } else {
-        println!("Found invalid value: {}", invalid);
+    if show_all {
+        for pos in find_invalid_positions(preamble_size, &values) {
+            println!("Invalid value {} at {}", values[pos], pos);
+        }
     }
 
-    let range = find_invalid_range(&values, invalid);
-    match range {
+    let invalid = match find_invalid_value(preamble_size, &values) {
+        Some(invalid) => invalid,
+        None => panic!("Could not find invalid value!"),
+    };
+    println!("Found invalid value: {}", invalid);
+
+    match find_invalid_range(&values, invalid) {
         Some((start, end)) => {
+            println!(
+                "Found run of invalids from {} with length {}",
+                start,
+                end - start
+            );
             let s = &values[start..end];
             let min = s.iter().min().unwrap();
             let max = s.iter().max().unwrap();
@@ -39,50 +54,204 @@ fn main() {
     };
 }
 
-fn find_invalid_range(values: &Vec<i64>, invalid: i64) -> Option<(usize, usize)> {
-    for start in 0..values.len() - 2 {
-        let mut length = 0;
-        let mut total = 0;
-        while start + length < values.len() {
-            total = total + &values[start + length];
-            length += 1;
-            if length >= 2 {
-                if total == invalid {
-                    println!(
-                        "Found run of invalids from {} with length {}",
-                        start, length
-                    );
-                    return Some((start, start + length));
-                }
+/// The last `size` values, counted so that checking whether two of them
+/// sum to something takes one pass over the distinct values rather than
+/// every pair.
+struct Window {
+    size: usize,
+    order: VecDeque<i64>,
+    counts: HashMap<i64, usize>,
+}
+
+impl Window {
+    fn new(size: usize) -> Window {
+        Window {
+            size,
+            order: VecDeque::with_capacity(size + 1),
+            counts: HashMap::new(),
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.order.len() == self.size
+    }
+
+    fn push(&mut self, value: i64) {
+        self.order.push_back(value);
+        *self.counts.entry(value).or_insert(0) += 1;
+        if self.order.len() > self.size {
+            let old = self.order.pop_front().unwrap();
+            let count = self.counts.get_mut(&old).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&old);
             }
         }
     }
-    return None;
+
+    /// Whether two different entries, which may hold the same value, add
+    /// up to `target`.
+    fn has_pair_summing_to(&self, target: i64) -> bool {
+        self.counts.keys().any(|&x| match target.checked_sub(x) {
+            Some(y) if y == x => self.counts[&x] >= 2,
+            Some(y) => self.counts.contains_key(&y),
+            None => false,
+        })
+    }
 }
 
-fn find_invalid_value(preamble_size: usize, values: Vec<i64>) -> i64 {
-    let mut buffer: Vec<i64> = Vec::new();
-
-    for value in values {
-        if buffer.len() >= preamble_size {
-            let mut found = false;
-            for i in 0..preamble_size {
-                for j in (i + 1)..preamble_size {
-                    if buffer[i] + buffer[j] == value {
-                        found = true;
-                    }
-                }
-            }
-            if !found {
-                return value;
-            }
+/// Positions of every value after the preamble that isn't the sum of two
+/// of the `preamble_size` values before it.
+fn find_invalid_positions(preamble_size: usize, values: &[i64]) -> Vec<usize> {
+    let mut window = Window::new(preamble_size);
+    let mut invalid = Vec::new();
+    for (pos, &value) in values.iter().enumerate() {
+        if window.is_full() && !window.has_pair_summing_to(value) {
+            invalid.push(pos);
         }
+        window.push(value);
+    }
+    invalid
+}
+
+fn find_invalid_value(preamble_size: usize, values: &[i64]) -> Option<i64> {
+    find_invalid_positions(preamble_size, values)
+        .first()
+        .map(|&pos| values[pos])
+}
 
-        buffer.push(value);
-        while buffer.len() > preamble_size {
-            buffer.remove(0);
+/// Finds a run of at least two values adding up to `target`, as a
+/// half-open range ending as early as possible.
+fn find_invalid_range(values: &[i64], target: i64) -> Option<(usize, usize)> {
+    if values.iter().any(|&v| v < 0) {
+        return find_range_by_prefix_sums(values, target);
+    }
+    // with nothing negative, growing the run on the right only ever makes
+    // it bigger and shrinking it from the left only ever makes it smaller.
+    // The total is an i128 so two big values can't overflow it.
+    let target = target as i128;
+    let mut start = 0;
+    let mut total: i128 = 0;
+    for end in 0..values.len() {
+        total += values[end] as i128;
+        while total > target && start <= end {
+            total -= values[start] as i128;
+            start += 1;
+        }
+        if total == target && end + 1 - start >= 2 {
+            return Some((start, end + 1));
         }
     }
+    None
+}
 
-    return 0;
+/// Any values at all: a run `start..end` adds up to `target` when the sum
+/// of everything before `end` is `target` more than the sum before
+/// `start`. Sums are kept in i128 so they can't overflow.
+fn find_range_by_prefix_sums(values: &[i64], target: i64) -> Option<(usize, usize)> {
+    // earliest position each sum of the values before it was seen at
+    let mut starts: HashMap<i128, usize> = HashMap::new();
+    let mut sums: Vec<i128> = vec![0];
+    for (idx, &value) in values.iter().enumerate() {
+        sums.push(sums[idx] + value as i128);
+        // runs ending here have to start two or more values back
+        if idx >= 1 {
+            starts.entry(sums[idx - 1]).or_insert(idx - 1);
+        }
+        if let Some(&start) = starts.get(&(sums[idx + 1] - target as i128)) {
+            return Some((start, idx + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{find_invalid_positions, find_invalid_range, find_invalid_value, Window};
+
+    const SAMPLE: [i64; 20] = [
+        35, 20, 15, 25, 47, 40, 62, 55, 65, 95, 102, 117, 150, 182, 127, 219, 299, 277, 309, 576,
+    ];
+
+    #[test]
+    fn test_sample() {
+        assert_eq!(Some(127), find_invalid_value(5, &SAMPLE));
+        assert_eq!(Some((2, 6)), find_invalid_range(&SAMPLE, 127));
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        // zero is a real answer, not "nothing found"
+        assert_eq!(Some(0), find_invalid_value(2, &[1, 2, 0]));
+        assert_eq!(None, find_invalid_value(2, &[1, 2, 3, 5, 8]));
+        assert_eq!(None, find_invalid_value(5, &[1, 2]));
+        assert_eq!(vec![2, 4], find_invalid_positions(2, &[1, 2, 4, 6, 20, 26]));
+
+        // the pair has to be two entries, but they can be equal
+        assert_eq!(Some(10), find_invalid_value(2, &[5, 1, 10]));
+        assert_eq!(None, find_invalid_value(2, &[5, 5, 10]));
+
+        let mut window = Window::new(2);
+        window.push(i64::MIN);
+        window.push(1);
+        assert!(!window.has_pair_summing_to(i64::MAX));
+        window.push(3);
+        assert!(window.has_pair_summing_to(4));
+        assert!(!window.has_pair_summing_to(i64::MIN + 1));
+    }
+
+    #[test]
+    fn test_invalid_range() {
+        // a single value equal to the target doesn't count
+        assert_eq!(Some((1, 3)), find_invalid_range(&[7, 3, 4, 7], 7));
+        assert_eq!(None, find_invalid_range(&[7, 8, 9], 7));
+        assert_eq!(Some((0, 2)), find_invalid_range(&[7, 0, 1], 7));
+        assert_eq!(None, find_invalid_range(&[], 7));
+        assert_eq!(Some((0, 2)), find_invalid_range(&[0, 0], 0));
+        assert_eq!(None, find_invalid_range(&[i64::MAX, i64::MAX, 3], 7));
+        assert_eq!(Some((1, 3)), find_invalid_range(&[i64::MAX, 3, 4], 7));
+        assert_eq!(
+            Some((1, 3)),
+            find_invalid_range(&[1, 1, i64::MAX - 1], i64::MAX)
+        );
+
+        // negative values need the slower search
+        assert_eq!(Some((0, 3)), find_invalid_range(&[10, -5, 2], 7));
+        assert_eq!(Some((1, 3)), find_invalid_range(&[1, -3, 10, 4], 7));
+        assert_eq!(None, find_invalid_range(&[7, -1], 7));
+        assert_eq!(
+            Some((0, 3)),
+            find_invalid_range(&[i64::MIN, i64::MAX, 1], 0)
+        );
+    }
+
+    #[test]
+    fn test_invalid_range_matches_brute_force() {
+        let brute_force = |values: &[i64], target: i64| {
+            (2..=values.len())
+                .flat_map(|end| (0..=end - 2).map(move |start| (start, end)))
+                .find(|&(start, end)| values[start..end].iter().sum::<i64>() == target)
+        };
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |range: i64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % range as u64) as i64
+        };
+        for round in 0..500 {
+            let len = next(12) as usize;
+            // every other round allows negative values
+            let low = if round % 2 == 0 { 0 } else { -10 };
+            let values: Vec<i64> = (0..len).map(|_| low + next(20)).collect();
+            let target = next(30) - 5;
+            assert_eq!(
+                brute_force(&values, target),
+                find_invalid_range(&values, target),
+                "{:?} {}",
+                values,
+                target
+            );
+        }
+    }
 }
@@ -0,0 +1,124 @@
+use std::io::{self, BufRead, Lines};
+
+/// A `key:value` pair, or the token that couldn't be split into one.
+pub type KeyValue<'a> = Result<(&'a str, &'a str), &'a str>;
+
+/// A group of consecutive non-blank lines from a batch file.
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    pub first_line: usize,
+    pub lines: Vec<String>,
+}
+
+impl Record {
+    pub fn last_line(&self) -> usize {
+        self.first_line + self.lines.len() - 1
+    }
+
+    /// Splits the record into whitespace separated `key:value` pairs, along
+    /// with the line each came from. Tokens without a `:` are returned as
+    /// errors.
+    pub fn key_values(&self) -> impl Iterator<Item = (usize, KeyValue<'_>)> {
+        let first_line = self.first_line;
+        self.lines.iter().enumerate().flat_map(move |(idx, line)| {
+            line.split_whitespace()
+                .map(move |token| (first_line + idx, token.split_once(':').ok_or(token)))
+        })
+    }
+}
+
+pub struct Records<R> {
+    lines: Lines<R>,
+    line_number: usize,
+}
+
+/// Groups the lines of `reader` into records separated by one or more blank
+/// lines. The final record doesn't need a trailing blank line.
+pub fn records<R: BufRead>(reader: R) -> Records<R> {
+    Records {
+        lines: reader.lines(),
+        line_number: 0,
+    }
+}
+
+impl<R: BufRead> Iterator for Records<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<io::Result<Record>> {
+        let mut record: Option<Record> = None;
+        for r in &mut self.lines {
+            self.line_number += 1;
+            let line = match r {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            let line = line.trim_end();
+            if line.trim_start().is_empty() {
+                if record.is_some() {
+                    break;
+                }
+            } else {
+                let line_number = self.line_number;
+                record
+                    .get_or_insert_with(|| Record {
+                        first_line: line_number,
+                        lines: Vec::new(),
+                    })
+                    .lines
+                    .push(line.to_string());
+            }
+        }
+        record.map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{records, KeyValue, Record};
+
+    fn read_all(input: &str) -> Vec<Record> {
+        records(input.as_bytes()).map(|r| r.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_records() {
+        let rs = read_all("abc\n\na\nb\n\n\n\nab\nac\n");
+        assert_eq!(3, rs.len());
+        assert_eq!(1, rs[0].first_line);
+        assert_eq!(vec!["abc"], rs[0].lines);
+        assert_eq!(3, rs[1].first_line);
+        assert_eq!(4, rs[1].last_line());
+        assert_eq!(vec!["a", "b"], rs[1].lines);
+        assert_eq!(8, rs[2].first_line);
+        assert_eq!(vec!["ab", "ac"], rs[2].lines);
+    }
+
+    #[test]
+    fn test_trailing_record_and_crlf() {
+        let rs = read_all("\r\n\r\nx:1 y:2\r\nz:3\r\n  \r\nw:4");
+        assert_eq!(2, rs.len());
+        assert_eq!(3, rs[0].first_line);
+        assert_eq!(vec!["x:1 y:2", "z:3"], rs[0].lines);
+        assert_eq!(6, rs[1].first_line);
+        assert_eq!(vec!["w:4"], rs[1].lines);
+
+        assert!(read_all("").is_empty());
+        assert!(read_all("\n\n").is_empty());
+    }
+
+    #[test]
+    fn test_key_values() {
+        let rs = read_all("ecl:gry pid:860033327\n  hcl:#fffffd  junk\nurl:http://x\n");
+        let kvs: Vec<(usize, KeyValue)> = rs[0].key_values().collect();
+        assert_eq!(
+            vec![
+                (1, Ok(("ecl", "gry"))),
+                (1, Ok(("pid", "860033327"))),
+                (2, Ok(("hcl", "#fffffd"))),
+                (2, Err("junk")),
+                (3, Ok(("url", "http://x"))),
+            ],
+            kvs
+        );
+    }
+}